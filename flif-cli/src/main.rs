@@ -3,7 +3,7 @@ use std::io::Write;
 use std::io::{BufReader, BufWriter};
 
 use flif::components::ColorSpace;
-use flif::{Decoder, FlifInfo};
+use flif::{Decoder, Encoder, EncoderOptions, FlifInfo};
 use flif::{Error, Result};
 use png::HasParameters;
 use structopt::StructOpt;
@@ -11,6 +11,7 @@ use structopt::StructOpt;
 #[derive(StructOpt, Debug)]
 #[structopt(name = "flif")]
 struct Args {
+    #[allow(dead_code)]
     #[structopt(short = "v", long = "verbose")]
     verbose: bool,
     #[structopt(subcommand)]
@@ -33,7 +34,32 @@ enum Command {
         output: Option<String>,
     },
     #[structopt(name = "encode")]
-    Encode {},
+    Encode {
+        #[structopt(
+            short = "I",
+            long = "interlace",
+            help = "write an interlaced FLIF that can be previewed while loading"
+        )]
+        interlace: bool,
+        #[structopt(
+            short = "Q",
+            long = "quality",
+            default_value = "100",
+            help = "quality from 0 to 100, anything below 100 is lossy"
+        )]
+        quality: u8,
+        #[structopt(
+            short = "E",
+            long = "effort",
+            default_value = "2",
+            help = "number of passes spent learning the MANIAC trees"
+        )]
+        effort: u8,
+        #[structopt(name = "INPUT", help = "Input PNG file")]
+        input: String,
+        #[structopt(name = "OUTPUT", help = "Output file")]
+        output: String,
+    },
 }
 
 fn main() {
@@ -45,7 +71,13 @@ fn main() {
             input,
             output,
         } => decode(identify, &input, output),
-        Command::Encode { .. } => encode(),
+        Command::Encode {
+            interlace,
+            quality,
+            effort,
+            input,
+            output,
+        } => encode(interlace, quality, effort, &input, &output),
     };
 
     std::process::exit(match result {
@@ -72,8 +104,7 @@ fn decode(identify: bool, input: &str, output: Option<String>) -> Result<()> {
 
             let info = image.info();
 
-            let mut encoder =
-                png::Encoder::new(w, info.header.width as u32, info.header.height as u32);
+            let mut encoder = png::Encoder::new(w, info.header.width, info.header.height);
 
             let color_type = match info.header.channels {
                 ColorSpace::RGBA => png::ColorType::RGBA,
@@ -81,15 +112,24 @@ fn decode(identify: bool, input: &str, output: Option<String>) -> Result<()> {
                 ColorSpace::Monochrome => png::ColorType::Grayscale,
             };
 
-            encoder.set(color_type).set(png::BitDepth::Eight);
-            let mut writer = encoder.write_header().unwrap();
-
-            // Get the raw pixel array of the FLIF image
-            let data = image.raw();
-            // Save as PNG
-            writer.write_image_data(&data).unwrap();
+            if let Some(data) = image.raw_u16() {
+                encoder.set(color_type).set(png::BitDepth::Sixteen);
+                let mut writer = encoder.write_header().unwrap();
+
+                // PNG stores 16 bit samples in big endian byte order
+                let data: Vec<u8> = data.iter().flat_map(|v| v.to_be_bytes()).collect();
+                writer.write_image_data(&data).unwrap();
+            } else {
+                encoder.set(color_type).set(png::BitDepth::Eight);
+                let mut writer = encoder.write_header().unwrap();
+
+                // Get the raw pixel array of the FLIF image
+                let data = image.raw();
+                // Save as PNG
+                writer.write_image_data(data).unwrap();
+            }
         } else {
-            std::io::stdout().write_all(&image.raw())?;
+            std::io::stdout().write_all(image.raw())?;
         }
     }
     Ok(())
@@ -116,8 +156,56 @@ fn id_file(info: &FlifInfo) {
     }
 }
 
-fn encode() -> Result<()> {
-    Err(Error::Unimplemented(
-        "flif.rs does not currently support encoding",
-    ))
+fn encode(interlace: bool, quality: u8, effort: u8, input: &str, output: &str) -> Result<()> {
+    let mut decoder = png::Decoder::new(BufReader::new(File::open(input)?));
+    // palettes and low bit depths are expanded to plain 8 bit samples
+    decoder.set(png::Transformations::EXPAND);
+    let (info, mut reader) = decoder
+        .read_info()
+        .map_err(|e| Error::InvalidOperation(format!("unable to read PNG: {}", e)))?;
+    let mut data = vec![0; info.buffer_size()];
+    reader
+        .next_frame(&mut data)
+        .map_err(|e| Error::InvalidOperation(format!("unable to read PNG: {}", e)))?;
+
+    if info.bit_depth != png::BitDepth::Eight {
+        return Err(Error::Unimplemented(
+            "only PNG images with 8 bits per channel can be encoded",
+        ));
+    }
+
+    let (channels, data) = match info.color_type {
+        png::ColorType::Grayscale => (ColorSpace::Monochrome, data),
+        png::ColorType::RGB => (ColorSpace::RGB, data),
+        png::ColorType::RGBA => (ColorSpace::RGBA, data),
+        png::ColorType::GrayscaleAlpha => {
+            let rgba = data
+                .chunks(2)
+                .flat_map(|ga| vec![ga[0], ga[0], ga[0], ga[1]])
+                .collect();
+            (ColorSpace::RGBA, rgba)
+        }
+        png::ColorType::Indexed => {
+            return Err(Error::InvalidOperation(
+                "palette PNG was not expanded".into(),
+            ))
+        }
+    };
+
+    let writer = BufWriter::new(File::create(output)?);
+    let options = EncoderOptions {
+        effort,
+        ..Default::default()
+    };
+    let (mut writer, psnr) =
+        Encoder::with_options(writer, info.width, info.height, channels, options)?
+            .interlaced(interlace)
+            .quality(quality)
+            .encode_with_psnr(&data)?;
+    writer.flush()?;
+
+    if quality < 100 {
+        println!("PSNR: {:.2} dB", psnr);
+    }
+    Ok(())
 }
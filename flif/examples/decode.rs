@@ -25,7 +25,7 @@ fn decode_and_write(input: &str, output: &str) {
     let file = File::create(output).unwrap();
     let w = &mut BufWriter::new(file);
 
-    let mut encoder = png::Encoder::new(w, info.header.width as u32, info.header.height as u32);
+    let mut encoder = png::Encoder::new(w, info.header.width, info.header.height);
 
     let color_type = match info.header.channels {
         ColorSpace::RGBA => png::ColorType::RGBA,
@@ -0,0 +1,1413 @@
+use std::io::Read;
+
+use num_traits::PrimInt;
+
+use crate::components::transformations::ColorRange;
+use crate::components::transformations::Transform;
+use crate::error::{Error, Result};
+use crate::frame::FrameData;
+use crate::maniac::{core_pvec, edge_pvec, interlaced_pvec, ManiacTree};
+use crate::numbers::chances::{ChanceTable, UpdateTable};
+use crate::numbers::median3;
+use crate::numbers::near_zero::NearZeroCoder;
+use crate::numbers::rac::Rac;
+use crate::numbers::symbol::UniformSymbolCoder;
+use crate::pixels::{ChannelsTrait, Pixel};
+use crate::pixels::{ColorValue, Rgba, RgbaChannels};
+use crate::{FlifInfo, Limits};
+
+/// Highest predictor id that can be selected for an interlaced pass
+const MAX_PREDICTOR: u8 = 2;
+
+/// Zoomlevels the encoder codes with a single context before learning the MANIAC trees
+const ROUGH_ZOOMLEVELS: usize = 13;
+
+pub(crate) type ManiacForest = [Option<ManiacTree>; 5];
+
+/// Source or sink of every symbol in the pixel data. The decoder reads the symbols, the
+/// encoder writes the values it is handed, so both traverse an image in the same order.
+pub(crate) trait PixelCoder {
+    /// Codes the MANIAC tree used for `channel`
+    fn code_tree(
+        &mut self,
+        channel: RgbaChannels,
+        info: &FlifInfo,
+        update_table: &UpdateTable,
+        limits: &Limits,
+    ) -> Result<ManiacTree>;
+
+    /// Codes a pixel `value` of `channel` predicted to be `guess`, returning the value that
+    /// ends up in the image
+    fn code_pixel(
+        &mut self,
+        channel: RgbaChannels,
+        maniac: &mut ManiacTree,
+        pvec: &[ColorValue],
+        guess: ColorValue,
+        range: ColorRange,
+        value: ColorValue,
+    ) -> Result<ColorValue>;
+
+    fn code_near_zero(
+        &mut self,
+        range: ColorRange,
+        value: ColorValue,
+        context: &mut ChanceTable,
+    ) -> Result<ColorValue>;
+
+    fn code_val<T: PrimInt>(&mut self, min: T, max: T, value: T) -> Result<T>;
+
+    fn code_bool(&mut self, value: bool) -> Result<bool>;
+
+    /// Whether the coder ran out of input, only a decoder reading a truncated file does
+    fn exhausted(&self) -> bool {
+        false
+    }
+
+    /// Whether at least `bytes` more bytes of input are there. A decoder that is fed its input
+    /// piece by piece codes a part of the image that might take more than that tentatively.
+    fn has_input(&self, _bytes: usize) -> bool {
+        true
+    }
+
+    /// Remembers where the coder is in its input, for `rollback`
+    fn checkpoint(&mut self) {}
+
+    /// Goes back to the last `checkpoint` if the input ran out since, returns whether it did
+    fn rollback(&mut self) -> bool {
+        false
+    }
+}
+
+impl<R: Read> PixelCoder for Rac<R> {
+    fn code_tree(
+        &mut self,
+        channel: RgbaChannels,
+        info: &FlifInfo,
+        update_table: &UpdateTable,
+        limits: &Limits,
+    ) -> Result<ManiacTree> {
+        ManiacTree::new(self, channel, info, update_table, limits)
+    }
+
+    fn code_pixel(
+        &mut self,
+        _channel: RgbaChannels,
+        maniac: &mut ManiacTree,
+        pvec: &[ColorValue],
+        guess: ColorValue,
+        range: ColorRange,
+        _value: ColorValue,
+    ) -> Result<ColorValue> {
+        maniac.process(self, pvec, guess, range.min, range.max)
+    }
+
+    fn code_near_zero(
+        &mut self,
+        range: ColorRange,
+        _value: ColorValue,
+        context: &mut ChanceTable,
+    ) -> Result<ColorValue> {
+        self.read_near_zero(range.min, range.max, context)
+    }
+
+    fn code_val<T: PrimInt>(&mut self, min: T, max: T, _value: T) -> Result<T> {
+        self.read_val(min, max)
+    }
+
+    fn code_bool(&mut self, _value: bool) -> Result<bool> {
+        self.read_bool()
+    }
+
+    fn exhausted(&self) -> bool {
+        self.is_truncated()
+    }
+}
+
+/// Where coding an image continues, kept between calls to `CodingImage::advance`
+#[derive(Debug)]
+pub(crate) enum Position {
+    Start,
+    /// The MANIAC trees of the first `coded` planes in `maniac_init_order` are done. An
+    /// interlaced image continues below zoomlevel `rough_zl` once all of them are.
+    Trees {
+        coded: usize,
+        rough_zl: Option<usize>,
+    },
+    /// Row `y` of the plane at `plane` in `get_chan_order` is next, non-interlaced images only
+    Rows {
+        plane: usize,
+        y: u32,
+    },
+    Pass(Pass),
+    Done,
+}
+
+/// Progress through an interlaced pass from `begin_zl` down to `end_zl`
+#[derive(Debug)]
+pub(crate) struct Pass {
+    begin_zl: usize,
+    end_zl: usize,
+    /// whether the top left pixel still has to be coded
+    first_pixel: bool,
+    /// `None` until the plane order and the predictors at the start of the pass are coded
+    predictors: Option<[i8; 5]>,
+    /// steps of the default order, `None` if the order of the planes is coded
+    order: Option<Vec<(usize, usize)>>,
+    /// zoomlevel every plane reached when the order is coded
+    zoomlevels: [usize; 5],
+    /// number of steps started so far
+    steps: usize,
+    step: Option<Step>,
+}
+
+impl Pass {
+    fn new(begin_zl: usize, end_zl: usize, zooms: usize) -> Pass {
+        Pass {
+            begin_zl,
+            end_zl,
+            first_pixel: begin_zl == zooms && end_zl > 0,
+            predictors: None,
+            order: None,
+            zoomlevels: [begin_zl + 1; 5],
+            steps: 0,
+            step: None,
+        }
+    }
+}
+
+/// A single plane at a single zoomlevel, with row `row` coming next
+#[derive(Debug)]
+struct Step {
+    plane: usize,
+    z: usize,
+    predictor: u8,
+    row: u32,
+}
+
+/// What a call to `CodingImage::advance` coded
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub(crate) enum Advance {
+    /// Nothing, the coder has to get more input first
+    Suspended,
+    /// A part that finishes neither a row nor a zoomlevel, like a tree or the start of a pass
+    Other,
+    /// The top left pixel of an interlaced image, all there is at the highest zoomlevel
+    FirstPixel(usize),
+    /// Row `y` of every frame in a plane of a non-interlaced image, `last` for the last plane
+    Row {
+        y: u32,
+        last: bool,
+    },
+    /// The plane at `plane` in `maniac_init_order` of an interlaced image reached zoomlevel `z`
+    Zoomlevel {
+        plane: usize,
+        z: usize,
+    },
+    Done,
+}
+
+pub(crate) struct CodingImage<'a, P: Pixel, C: PixelCoder> {
+    height: u32,
+    width: u32,
+    frames: usize,
+    info: &'a FlifInfo,
+    coder: &'a mut C,
+    update_table: &'a UpdateTable,
+    limits: &'a Limits,
+    data: Vec<P>,
+}
+
+#[derive(Debug)]
+pub(crate) struct EdgePixelVicinity<P: Pixel> {
+    pub pixel: P,
+    pub chan: P::Channels,
+    pub left: Option<ColorValue>,
+    pub left2: Option<ColorValue>,
+    pub top: Option<ColorValue>,
+    pub top2: Option<ColorValue>,
+    pub top_left: Option<ColorValue>,
+    pub top_right: Option<ColorValue>,
+}
+
+#[derive(Debug)]
+pub(crate) struct CorePixelVicinity<P: Pixel> {
+    pub pixel: P,
+    pub chan: P::Channels,
+    pub left: ColorValue,
+    pub left2: ColorValue,
+    pub top: ColorValue,
+    pub top2: ColorValue,
+    pub top_left: ColorValue,
+    pub top_right: ColorValue,
+}
+
+/// Neighbourhood of a pixel decoded during an interlaced pass. Vertical passes (odd
+/// zoomlevels) see the image transposed, so `top` and `bottom` are always the two known
+/// neighbours the pixel is interpolated from.
+#[derive(Debug)]
+pub(crate) struct InterlacedPixelVicinity<P: Pixel> {
+    pub pixel: P,
+    pub chan: P::Channels,
+    pub top: ColorValue,
+    pub bottom: ColorValue,
+    pub left: ColorValue,
+    pub top_left: ColorValue,
+    pub top_right: ColorValue,
+    pub bottom_left: ColorValue,
+    pub bottom_right: ColorValue,
+    pub top2: Option<ColorValue>,
+    pub left2: Option<ColorValue>,
+    /// difference between the luma of this pixel and its interpolation, chroma planes only
+    pub luma_miss: Option<ColorValue>,
+}
+
+// safety criterias defined by `debug_assert`s
+impl<'a, P: Pixel, C: PixelCoder> CodingImage<'a, P, C> {
+    pub fn new(
+        info: &'a FlifInfo,
+        coder: &'a mut C,
+        limits: &'a Limits,
+        update_table: &'a UpdateTable,
+    ) -> Result<CodingImage<'a, P, C>> {
+        let frames = info.header.num_frames as usize;
+        let pixels = (info.header.height * info.header.width) as usize * frames;
+        Self::with_data(
+            info,
+            coder,
+            limits,
+            update_table,
+            vec![P::default(); pixels],
+        )
+    }
+
+    /// Creates an image from the transformed pixels of every frame, used when encoding
+    pub fn with_data(
+        info: &'a FlifInfo,
+        coder: &'a mut C,
+        limits: &'a Limits,
+        update_table: &'a UpdateTable,
+        data: Vec<P>,
+    ) -> Result<CodingImage<'a, P, C>> {
+        let frames = info.header.num_frames as usize;
+        let pixels = (info.header.height * info.header.width) as usize * frames;
+        if data.len() != pixels {
+            return Err(Error::InvalidOperation(format!(
+                "expected {} pixels but got {}",
+                pixels,
+                data.len()
+            )));
+        }
+
+        Ok(CodingImage {
+            height: info.header.height,
+            width: info.header.width,
+            frames,
+            info,
+            coder,
+            update_table,
+            limits,
+            data,
+        })
+    }
+
+    fn frame_size(&self) -> usize {
+        (self.width * self.height) as usize
+    }
+
+    fn check_data(&self) -> bool {
+        self.data.len() == self.frame_size() * self.frames
+    }
+
+    fn get_idx(&self, fr: usize, x: u32, y: u32) -> usize {
+        fr * self.frame_size() + ((self.width * y) + x) as usize
+    }
+
+    unsafe fn get_val(&self, fr: usize, x: u32, y: u32, chan: P::Channels) -> ColorValue {
+        debug_assert!(x < self.width && y < self.height && fr < self.frames && self.check_data());
+        self.data
+            .get_unchecked(self.get_idx(fr, x, y))
+            .get_value(chan)
+    }
+
+    unsafe fn get_edge_vicinity(
+        &self,
+        fr: usize,
+        x: u32,
+        y: u32,
+        chan: P::Channels,
+    ) -> EdgePixelVicinity<P> {
+        debug_assert!(x < self.width && y < self.height && fr < self.frames && self.check_data());
+        EdgePixelVicinity {
+            pixel: *self.data.get_unchecked(self.get_idx(fr, x, y)),
+            chan,
+            top: if y != 0 {
+                Some(self.get_val(fr, x, y - 1, chan))
+            } else {
+                None
+            },
+            left: if x != 0 {
+                Some(self.get_val(fr, x - 1, y, chan))
+            } else {
+                None
+            },
+            left2: if x > 1 {
+                Some(self.get_val(fr, x - 2, y, chan))
+            } else {
+                None
+            },
+            top2: if y > 1 {
+                Some(self.get_val(fr, x, y - 2, chan))
+            } else {
+                None
+            },
+            top_left: if x != 0 && y != 0 {
+                Some(self.get_val(fr, x - 1, y - 1, chan))
+            } else {
+                None
+            },
+            top_right: if y != 0 && x + 1 < self.width {
+                Some(self.get_val(fr, x + 1, y - 1, chan))
+            } else {
+                None
+            },
+        }
+    }
+
+    unsafe fn get_core_vicinity(
+        &self,
+        fr: usize,
+        x: u32,
+        y: u32,
+        chan: P::Channels,
+    ) -> CorePixelVicinity<P> {
+        debug_assert!(x < self.width - 1 && y < self.height && x > 1 && y > 1 && self.check_data());
+        CorePixelVicinity {
+            pixel: *self.data.get_unchecked(self.get_idx(fr, x, y)),
+            chan,
+            top: self.get_val(fr, x, y - 1, chan),
+            left: self.get_val(fr, x - 1, y, chan),
+            left2: self.get_val(fr, x - 2, y, chan),
+            top2: self.get_val(fr, x, y - 2, chan),
+            top_left: self.get_val(fr, x - 1, y - 1, chan),
+            top_right: self.get_val(fr, x + 1, y - 1, chan),
+        }
+    }
+
+    fn process_edge_pixel_safe(
+        &mut self,
+        fr: usize,
+        vic: EdgePixelVicinity<P>,
+        chan: P::Channels,
+        copied: Option<ColorValue>,
+        maniac: &mut Option<ManiacTree>,
+    ) -> Result<ColorValue> {
+        let c = chan.as_channel();
+        let pix = vic.pixel.to_rgba();
+        let range = self.crange(fr, c, pix);
+        let skip = self.is_invisible(&vic.pixel, chan);
+
+        Ok(if let Some(ref mut maniac) = maniac {
+            let guess = make_edge_guess(self.info, &vic);
+            if skip {
+                guess
+            } else if let Some(val) = copied {
+                val
+            } else {
+                let snap = self.snap(fr, c, pix, guess);
+                let pvec = edge_pvec(snap, &vic);
+                let value = vic.pixel.get_value(chan);
+                self.coder
+                    .code_pixel(c, maniac, &pvec, snap, range, value)?
+            }
+        } else {
+            range.min
+        })
+    }
+
+    fn process_core_pixel_safe(
+        &mut self,
+        fr: usize,
+        vic: CorePixelVicinity<P>,
+        chan: P::Channels,
+        copied: Option<ColorValue>,
+        maniac: &mut Option<ManiacTree>,
+    ) -> Result<ColorValue> {
+        let c = chan.as_channel();
+        let pix = vic.pixel.to_rgba();
+        let range = self.crange(fr, c, pix);
+        let skip = self.is_invisible(&vic.pixel, chan);
+
+        Ok(if let Some(ref mut maniac) = maniac {
+            let guess = make_core_guess(&vic);
+            if skip {
+                guess
+            } else if let Some(val) = copied {
+                val
+            } else {
+                let snap = self.snap(fr, c, pix, guess);
+                let pvec = core_pvec(snap, &vic);
+                let value = vic.pixel.get_value(chan);
+                self.coder
+                    .code_pixel(c, maniac, &pvec, snap, range, value)?
+            }
+        } else {
+            range.min
+        })
+    }
+
+    unsafe fn process_edge_pixel(
+        &mut self,
+        fr: usize,
+        x: u32,
+        y: u32,
+        chan: P::Channels,
+        maniac: &mut Option<ManiacTree>,
+    ) -> Result<()> {
+        let vic = self.get_edge_vicinity(fr, x, y, chan);
+        let copied = self.lookback_value(fr, x, y, &vic.pixel, chan);
+        let val = self.process_edge_pixel_safe(fr, vic, chan, copied, maniac)?;
+        let idx = self.get_idx(fr, x, y);
+        self.data.get_unchecked_mut(idx).set_value(val, chan);
+        Ok(())
+    }
+
+    unsafe fn process_core_pixel(
+        &mut self,
+        fr: usize,
+        x: u32,
+        y: u32,
+        chan: P::Channels,
+        maniac: &mut Option<ManiacTree>,
+    ) -> Result<()> {
+        let vic = self.get_core_vicinity(fr, x, y, chan);
+        let copied = self.lookback_value(fr, x, y, &vic.pixel, chan);
+        let val = self.process_core_pixel_safe(fr, vic, chan, copied, maniac)?;
+        let idx = self.get_idx(fr, x, y);
+        self.data.get_unchecked_mut(idx).set_value(val, chan);
+        Ok(())
+    }
+
+    /// Pixels with a frame lookback copy the values of an earlier frame
+    fn lookback_value(
+        &self,
+        fr: usize,
+        x: u32,
+        y: u32,
+        pixel: &P,
+        chan: P::Channels,
+    ) -> Option<ColorValue> {
+        let lookback = pixel.get_lookback();
+        if lookback <= 0 || chan.as_channel() == RgbaChannels::Lookback {
+            return None;
+        }
+
+        let idx = self.get_idx(fr.checked_sub(lookback as usize)?, x, y);
+        Some(self.data[idx].get_value(chan))
+    }
+
+    /// Color channels of invisible pixels aren't coded when `alpha_zero` is set
+    fn is_invisible(&self, pixel: &P, chan: P::Channels) -> bool {
+        self.info.second_header.alpha_zero
+            && (chan.as_channel() as usize) < 3
+            && pixel.is_alpha_zero()
+    }
+
+    fn crange(&self, fr: usize, channel: RgbaChannels, pix: Rgba) -> ColorRange {
+        let mut range = self.info.transform.crange(channel, pix);
+        // pixels can't look back past the first frame
+        if channel == RgbaChannels::Lookback {
+            range.max = range.max.min(fr as ColorValue);
+        }
+        range
+    }
+
+    fn snap(&self, fr: usize, channel: RgbaChannels, pix: Rgba, guess: ColorValue) -> ColorValue {
+        let snap = self.info.transform.snap(channel, pix, guess);
+        if channel == RgbaChannels::Lookback {
+            snap.min(fr as ColorValue)
+        } else {
+            snap
+        }
+    }
+
+    /// Columns `begin..end` of a row that are coded, the rest is copied from the previous frame
+    fn frame_shape(&self, fr: usize, y: u32) -> (u32, u32) {
+        self.info
+            .transform
+            .frame_shape(fr, y)
+            .unwrap_or((0, self.width))
+    }
+
+    fn copy_value(&mut self, from: usize, fr: usize, x: u32, y: u32, chan: P::Channels) {
+        let val = self.data[self.get_idx(from, x, y)].get_value(chan);
+        let idx = self.get_idx(fr, x, y);
+        self.data[idx].set_value(val, chan);
+    }
+
+    /// Codes every frame of the image
+    pub fn process(&mut self) -> Result<()> {
+        let mut position = Position::Start;
+        let mut maniac = ManiacForest::default();
+        loop {
+            match self.advance(&mut position, &mut maniac)? {
+                Advance::Done => return Ok(()),
+                Advance::Suspended => Err(Error::Incomplete)?,
+                _ => {}
+            }
+        }
+    }
+
+    /// Codes an interlaced image down to zoomlevel `end_zl`, stopping early when the coder
+    /// runs out of input. Every plane is scaled up from the finest zoomlevel it reached, the
+    /// coarsest of those is returned.
+    pub fn process_preview(&mut self, end_zl: usize) -> Result<usize> {
+        let reached = self.interlaced_process(end_zl)?;
+        let planes = P::maniac_init_order();
+        for (p, chan) in planes.as_ref().iter().enumerate() {
+            self.upscale(*chan, reached[p]);
+        }
+
+        Ok(reached.iter().cloned().max().unwrap_or(0))
+    }
+
+    /// Raw pixel data of every frame, with the transformations undone
+    pub fn frames(&self) -> Vec<FrameData> {
+        // undo transofrms and copy raw data
+        let n = self.info.header.channels as usize;
+        let frame_size = self.frame_size();
+        let high_depth = self
+            .info
+            .second_header
+            .bits_per_pixel
+            .iter()
+            .any(|&bits| bits > 8);
+        let mut frames = Vec::with_capacity(self.frames);
+        for frame in self.data.chunks(frame_size) {
+            let pixels = frame
+                .iter()
+                .map(|pixel| self.info.transform.undo(pixel.to_rgba()));
+            let data = if high_depth {
+                let mut raw = Vec::with_capacity(n * frame_size);
+                for rgba in pixels {
+                    raw.extend(rgba.0[..n].iter().map(|v| *v as u16));
+                }
+                FrameData::Sixteen(raw.into_boxed_slice())
+            } else {
+                let mut raw = Vec::with_capacity(n * frame_size);
+                for rgba in pixels {
+                    raw.extend(rgba.0[..n].iter().map(|v| *v as u8));
+                }
+                FrameData::Eight(raw.into_boxed_slice())
+            };
+            frames.push(data);
+        }
+
+        frames
+    }
+
+    /// Hands back the pixels, so that coding can continue with a new image later on
+    pub fn into_data(self) -> Vec<P> {
+        self.data
+    }
+
+    /// Decodes a still image and hands every row to `sink` as soon as it is final, with the
+    /// transformations undone and laid out like `Frame::raw`. Rows of a non-interlaced image
+    /// are final once the last plane reaches them, rows of an interlaced image only after the
+    /// final pass.
+    pub fn process_rows<F: FnMut(u32, &[u8])>(&mut self, mut sink: F) -> Result<()> {
+        let mut row = Vec::new();
+        let mut position = Position::Start;
+        let mut maniac = ManiacForest::default();
+        loop {
+            match self.advance(&mut position, &mut maniac)? {
+                Advance::Row { y, last: true } => {
+                    self.raw_row(0, y, &mut row);
+                    sink(y, &row);
+                }
+                Advance::Done => break,
+                Advance::Suspended => Err(Error::Incomplete)?,
+                _ => {}
+            }
+        }
+
+        if self.info.header.interlaced {
+            for y in 0..self.height {
+                self.raw_row(0, y, &mut row);
+                sink(y, &row);
+            }
+        }
+        Ok(())
+    }
+
+    /// Undoes the transformations of row `y` of frame `fr` into `raw`
+    pub fn raw_row(&self, fr: usize, y: u32, raw: &mut Vec<u8>) {
+        let n = self.info.header.channels as usize;
+        let high_depth = self
+            .info
+            .second_header
+            .bits_per_pixel
+            .iter()
+            .any(|&bits| bits > 8);
+        let start = self.get_idx(fr, 0, y);
+        let pixels = self.data[start..start + self.width as usize]
+            .iter()
+            .map(|pixel| self.info.transform.undo(pixel.to_rgba()));
+
+        raw.clear();
+        for rgba in pixels {
+            for v in &rgba.0[..n] {
+                if high_depth {
+                    raw.extend_from_slice(&(*v as u16).to_ne_bytes());
+                } else {
+                    raw.push(*v as u8);
+                }
+            }
+        }
+    }
+
+    /// Codes the part of the image at `position` and moves past it. A decoder that is fed its
+    /// input piece by piece can stop between any two parts and continue later on.
+    pub fn advance(
+        &mut self,
+        position: &mut Position,
+        maniac: &mut ManiacForest,
+    ) -> Result<Advance> {
+        let zooms = zoom_count(self.width, self.height);
+        match *position {
+            Position::Start if self.info.header.interlaced => {
+                let bytes = coded_bytes(val_decisions(zooms));
+                let rough_zl = self.tentatively(bytes, &mut None, |image, _| {
+                    let rough_zl = zooms.saturating_sub(ROUGH_ZOOMLEVELS);
+                    image.coder.code_val(0, zooms, rough_zl)
+                })?;
+                let rough_zl = match rough_zl {
+                    Some(rough_zl) => rough_zl,
+                    None => return Ok(Advance::Suspended),
+                };
+
+                // constant planes are never coded, so fill them in up front
+                let channels = P::maniac_init_order();
+                for chan in channels.as_ref() {
+                    let range = self.info.transform.range(chan.as_channel());
+                    if range.min == range.max {
+                        for pixel in self.data.iter_mut() {
+                            pixel.set_value(range.min, *chan);
+                        }
+                    } else {
+                        // the rough zoomlevels are coded with a single context per plane
+                        let leaf = ManiacTree::leaf(self.update_table);
+                        maniac[chan.as_channel() as usize] = Some(leaf);
+                    }
+                }
+                *position = Position::Pass(Pass::new(zooms, rough_zl + 1, zooms));
+            }
+            Position::Start => {
+                *position = Position::Trees {
+                    coded: 0,
+                    rough_zl: None,
+                };
+            }
+            Position::Trees { coded, rough_zl } => {
+                let channels = P::maniac_init_order();
+                if let Some(chan) = channels.as_ref().get(coded) {
+                    let channel = chan.as_channel();
+                    let range = self.info.transform.range(channel);
+                    maniac[channel as usize] = if range.min == range.max {
+                        None
+                    } else {
+                        // there is no telling how large a tree is
+                        let tree = self.tentatively(usize::MAX, &mut None, |image, _| {
+                            let (info, limits) = (image.info, image.limits);
+                            image
+                                .coder
+                                .code_tree(channel, info, image.update_table, limits)
+                        })?;
+                        match tree {
+                            Some(tree) => Some(tree),
+                            None => return Ok(Advance::Suspended),
+                        }
+                    };
+                    *position = Position::Trees {
+                        coded: coded + 1,
+                        rough_zl,
+                    };
+                } else if let Some(rough_zl) = rough_zl {
+                    *position = Position::Pass(Pass::new(rough_zl, 0, zooms));
+                } else {
+                    *position = Position::Rows { plane: 0, y: 0 };
+                }
+            }
+            Position::Rows { plane, y } => {
+                let channels = P::get_chan_order();
+                let channels = channels.as_ref();
+                let chan = channels[plane];
+                let range = self.info.transform.range(chan.as_channel());
+                let bytes =
+                    self.frames * self.width as usize * coded_bytes(near_zero_decisions(range));
+
+                // the rows of all frames are interleaved
+                let maniac = &mut maniac[chan.as_channel() as usize];
+                let coded = self.tentatively(bytes, maniac, |image, maniac| {
+                    for fr in 0..image.frames {
+                        image.row_pass(fr, y, chan, maniac)?;
+                    }
+                    Ok(())
+                })?;
+                if coded.is_none() {
+                    return Ok(Advance::Suspended);
+                }
+
+                let last = plane + 1 == channels.len();
+                *position = if y + 1 < self.height {
+                    Position::Rows { plane, y: y + 1 }
+                } else if !last {
+                    Position::Rows {
+                        plane: plane + 1,
+                        y: 0,
+                    }
+                } else {
+                    Position::Done
+                };
+                return Ok(Advance::Row { y, last });
+            }
+            Position::Pass(ref mut pass) => {
+                if let Some(advance) = self.advance_pass(pass, maniac)? {
+                    return Ok(advance);
+                }
+
+                // the rough pass stops right above the zoomlevel the trees are needed for
+                *position = match pass.end_zl {
+                    0 => Position::Done,
+                    end_zl => Position::Trees {
+                        coded: 0,
+                        rough_zl: Some(end_zl - 1),
+                    },
+                };
+            }
+            Position::Done => return Ok(Advance::Done),
+        }
+
+        Ok(Advance::Other)
+    }
+
+    /// Codes the next part of an interlaced pass, `None` once the pass is over
+    fn advance_pass(
+        &mut self,
+        pass: &mut Pass,
+        maniac: &mut ManiacForest,
+    ) -> Result<Option<Advance>> {
+        let planes = P::maniac_init_order();
+        let planes = planes.as_ref();
+
+        if pass.first_pixel {
+            let bytes: usize = planes
+                .iter()
+                .map(|chan| {
+                    let range = self.info.transform.range(chan.as_channel());
+                    self.frames * coded_bytes(near_zero_decisions(range))
+                })
+                .sum();
+            let coded = self.tentatively(bytes, &mut None, |image, _| {
+                // the top left pixel is all there is at the highest zoomlevel
+                let mut context = ChanceTable::new(image.update_table);
+                for chan in planes {
+                    let range = image.info.transform.range(chan.as_channel());
+                    if range.min < range.max {
+                        for fr in 0..image.frames {
+                            let idx = image.get_idx(fr, 0, 0);
+                            let value = image.data[idx].get_value(*chan);
+                            let val = image.coder.code_near_zero(range, value, &mut context)?;
+                            image.data[idx].set_value(val, *chan);
+                        }
+                    }
+                }
+                Ok(())
+            })?;
+            if coded.is_none() {
+                return Ok(Some(Advance::Suspended));
+            }
+
+            pass.first_pixel = false;
+            return Ok(Some(Advance::FirstPixel(pass.begin_zl)));
+        }
+
+        let predictors = match pass.predictors {
+            Some(predictors) => predictors,
+            None => {
+                let decisions = 1 + planes.len() * val_decisions(MAX_PREDICTOR as usize + 1);
+                let start = self.tentatively(coded_bytes(decisions), &mut None, |image, _| {
+                    // the encoder always sticks to the default order and a single predictor
+                    // per plane
+                    let default_order = image.coder.code_bool(true)?;
+                    let mut predictors = [0i8; 5];
+                    for predictor in predictors.iter_mut().take(planes.len()) {
+                        *predictor = image.coder.code_val(-1, MAX_PREDICTOR as i8, 0)?;
+                    }
+                    Ok((default_order, predictors))
+                })?;
+                let (default_order, predictors) = match start {
+                    Some(start) => start,
+                    None => return Ok(Some(Advance::Suspended)),
+                };
+
+                if default_order {
+                    let order = plane_zoomlevel_order(
+                        &self.info.transform,
+                        planes,
+                        pass.begin_zl,
+                        pass.end_zl,
+                    );
+                    pass.order = Some(order);
+                }
+                pass.predictors = Some(predictors);
+                return Ok(Some(Advance::Other));
+            }
+        };
+
+        let step = loop {
+            if let Some(ref mut step) = pass.step {
+                break step;
+            }
+            if pass.steps == planes.len() * (pass.begin_zl + 1).saturating_sub(pass.end_zl) {
+                return Ok(None);
+            }
+
+            let decisions = val_decisions(planes.len() - 1) + val_decisions(MAX_PREDICTOR as usize);
+            let order = pass.order.as_ref().map(|order| order[pass.steps]);
+            let start = self.tentatively(coded_bytes(decisions), &mut None, |image, _| {
+                let p = match order {
+                    Some((p, _)) => p,
+                    None => image.coder.code_val(0, planes.len() - 1, 0)?,
+                };
+                let range = image.info.transform.range(planes[p].as_channel());
+                let predictor = if range.min == range.max {
+                    None
+                } else if predictors[p] < 0 {
+                    Some(image.coder.code_val(0, MAX_PREDICTOR, 0)?)
+                } else {
+                    Some(predictors[p] as u8)
+                };
+                Ok((p, predictor))
+            })?;
+            let (p, predictor) = match start {
+                Some(start) => start,
+                None => return Ok(Some(Advance::Suspended)),
+            };
+
+            let z = match order {
+                Some((_, z)) => z,
+                None if pass.zoomlevels[p] <= pass.end_zl => {
+                    return Err(Error::InvalidOperation(
+                        "interlaced plane order reached past the final zoomlevel".into(),
+                    ));
+                }
+                None => {
+                    pass.zoomlevels[p] -= 1;
+                    pass.zoomlevels[p]
+                }
+            };
+            pass.steps += 1;
+
+            // constant planes have nothing to code
+            if let Some(predictor) = predictor {
+                // even zoomlevels fill in the odd rows, odd zoomlevels the odd columns
+                let row = if z % 2 == 1 { 0 } else { 1 };
+                pass.step = Some(Step {
+                    plane: p,
+                    z,
+                    predictor,
+                    row,
+                });
+            }
+        };
+
+        let chan = planes[step.plane];
+        let rows = zoom_rows(self.height, step.z);
+        if step.row < rows {
+            let range = self.info.transform.range(chan.as_channel());
+            let pixels = self.frames * zoom_cols(self.width, step.z) as usize;
+            let bytes = pixels * coded_bytes(near_zero_decisions(range));
+            let maniac = &mut maniac[chan.as_channel() as usize];
+            let (z, r, predictor) = (step.z, step.row, step.predictor);
+            let coded = self.tentatively(bytes, maniac, |image, maniac| {
+                for fr in 0..image.frames {
+                    image.interlaced_row(fr, z, r, chan, predictor, maniac)?;
+                }
+                Ok(())
+            })?;
+            if coded.is_none() {
+                return Ok(Some(Advance::Suspended));
+            }
+
+            step.row += if step.z % 2 == 1 { 1 } else { 2 };
+            if step.row < rows {
+                return Ok(Some(Advance::Other));
+            }
+        }
+
+        let (plane, z) = (step.plane, step.z);
+        pass.step = None;
+        Ok(Some(Advance::Zoomlevel { plane, z }))
+    }
+
+    /// Codes a part of the image with `code`, which takes `maniac` along. If the coder might
+    /// run out of input within the next `bytes`, the part is coded tentatively and undone
+    /// again if it does, returning `None`.
+    fn tentatively<T, F>(
+        &mut self,
+        bytes: usize,
+        maniac: &mut Option<ManiacTree>,
+        code: F,
+    ) -> Result<Option<T>>
+    where
+        F: FnOnce(&mut Self, &mut Option<ManiacTree>) -> Result<T>,
+    {
+        if self.coder.has_input(bytes) {
+            return code(self, maniac).map(Some);
+        }
+
+        self.coder.checkpoint();
+        if let Some(ref mut maniac) = *maniac {
+            maniac.checkpoint();
+        }
+        let result = code(self, maniac);
+        let rolled_back = self.coder.rollback();
+        if let Some(ref mut maniac) = *maniac {
+            if rolled_back {
+                maniac.rollback();
+            } else {
+                maniac.commit();
+            }
+        }
+
+        if rolled_back {
+            Ok(None)
+        } else {
+            result.map(Some)
+        }
+    }
+
+    /// Codes the zoomlevels of every plane down to `end_zl` and returns the finest zoomlevel
+    /// each plane reached, stopping at the first part of the image the coder runs out of
+    /// input in
+    fn interlaced_process(&mut self, end_zl: usize) -> Result<[usize; 5]> {
+        let zooms = zoom_count(self.width, self.height);
+        let planes = P::maniac_init_order();
+        let planes = planes.as_ref().len();
+        let mut reached = [0; 5];
+        for (p, chan) in P::maniac_init_order().as_ref().iter().enumerate() {
+            let range = self.info.transform.range(chan.as_channel());
+            if range.min < range.max {
+                reached[p] = zooms + 1;
+            }
+        }
+
+        let mut position = Position::Start;
+        let mut maniac = ManiacForest::default();
+        while reached[..planes].iter().any(|&zl| zl > end_zl) {
+            let advance = match self.advance(&mut position, &mut maniac) {
+                // whatever got decoded from past the end of the input is meaningless
+                Err(_) if self.coder.exhausted() => break,
+                advance => advance?,
+            };
+            if self.coder.exhausted() {
+                break;
+            }
+
+            match advance {
+                Advance::FirstPixel(z) => {
+                    for zl in reached.iter_mut().take(planes) {
+                        *zl = (*zl).min(z);
+                    }
+                }
+                Advance::Zoomlevel { plane, z } => reached[plane] = z,
+                Advance::Done | Advance::Suspended => break,
+                _ => {}
+            }
+        }
+
+        Ok(reached)
+    }
+
+    fn row_pass(
+        &mut self,
+        fr: usize,
+        y: u32,
+        chan: P::Channels,
+        maniac: &mut Option<ManiacTree>,
+    ) -> Result<()> {
+        let width = self.width;
+        if let Some(original) = self.info.transform.seen_before(fr) {
+            for x in 0..width {
+                self.copy_value(original, fr, x, y, chan);
+            }
+            return Ok(());
+        }
+
+        let (begin, end) = self.frame_shape(fr, y);
+        if fr > 0 {
+            for x in (0..begin).chain(end..width) {
+                self.copy_value(fr - 1, fr, x, y, chan);
+            }
+        }
+
+        // safe because we are sure that x and y inside the image
+        unsafe {
+            // special case for small images and the first two rows
+            if width <= 3 || y < 2 {
+                for x in begin..end {
+                    self.process_edge_pixel(fr, x, y, chan, maniac)?;
+                }
+                return Ok(());
+            }
+
+            for x in begin..end.min(2) {
+                self.process_edge_pixel(fr, x, y, chan, maniac)?;
+            }
+            for x in begin.max(2)..end.min(width - 1) {
+                self.process_core_pixel(fr, x, y, chan, maniac)?;
+            }
+            for x in begin.max(width - 1)..end {
+                self.process_edge_pixel(fr, x, y, chan, maniac)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Fills in the pixels of `chan` that are not on the grid of zoomlevel `z` with their
+    /// nearest neighbour on it
+    fn upscale(&mut self, chan: P::Channels, z: usize) {
+        let z = z.min(zoom_count(self.width, self.height));
+        let (row_shift, col_shift) = (row_shift(z), col_shift(z));
+        for fr in 0..self.frames {
+            for y in 0..self.height {
+                for x in 0..self.width {
+                    let (grid_x, grid_y) =
+                        ((x >> col_shift) << col_shift, (y >> row_shift) << row_shift);
+                    if (grid_x, grid_y) != (x, y) {
+                        let val = self.data[self.get_idx(fr, grid_x, grid_y)].get_value(chan);
+                        let idx = self.get_idx(fr, x, y);
+                        self.data[idx].set_value(val, chan);
+                    }
+                }
+            }
+        }
+    }
+
+    fn interlaced_row(
+        &mut self,
+        fr: usize,
+        z: usize,
+        r: u32,
+        chan: P::Channels,
+        predictor: u8,
+        maniac: &mut Option<ManiacTree>,
+    ) -> Result<()> {
+        let channel = chan.as_channel();
+        let y = r << row_shift(z);
+        let cols = zoom_cols(self.width, z);
+        let (first_col, step) = if z % 2 == 1 { (1, 2) } else { (0, 1) };
+
+        if let Some(original) = self.info.transform.seen_before(fr) {
+            for c in (first_col..cols).step_by(step) {
+                self.copy_value(original, fr, c << col_shift(z), y, chan);
+            }
+            return Ok(());
+        }
+
+        let (begin, end) = match self.frame_shape(fr, y) {
+            (begin, end) if begin < end => (begin >> col_shift(z), 1 + ((end - 1) >> col_shift(z))),
+            _ => (0, 0),
+        };
+
+        for c in (first_col..cols).step_by(step) {
+            let x = c << col_shift(z);
+            if c < begin || c >= end {
+                if fr > 0 {
+                    self.copy_value(fr - 1, fr, x, y, chan);
+                }
+                continue;
+            }
+
+            // safe because zoomlevel coordinates always map inside the image
+            let vic = unsafe { self.get_interlaced_vicinity(fr, z, r, c, chan) };
+
+            let pix = vic.pixel.to_rgba();
+            let range = self.crange(fr, channel, pix);
+            let skip = self.is_invisible(&vic.pixel, chan);
+            let copied = self.lookback_value(fr, x, y, &vic.pixel, chan);
+
+            let val = if let Some(ref mut maniac) = maniac {
+                if skip {
+                    let invisible = self.info.second_header.invis_pixel_predictor;
+                    make_interlaced_guess(&vic, invisible.unwrap_or(predictor))
+                } else if let Some(val) = copied {
+                    val
+                } else {
+                    let guess = make_interlaced_guess(&vic, predictor);
+                    let snap = self.snap(fr, channel, pix, guess);
+                    let pvec = interlaced_pvec(snap, &vic);
+                    let value = vic.pixel.get_value(chan);
+                    self.coder
+                        .code_pixel(channel, maniac, &pvec, snap, range, value)?
+                }
+            } else {
+                range.min
+            };
+
+            let idx = self.get_idx(fr, x, y);
+            self.data[idx].set_value(val, chan);
+        }
+        Ok(())
+    }
+
+    unsafe fn get_interlaced_vicinity(
+        &self,
+        fr: usize,
+        z: usize,
+        r: u32,
+        c: u32,
+        chan: P::Channels,
+    ) -> InterlacedPixelVicinity<P> {
+        let rows = zoom_rows(self.height, z);
+        let cols = zoom_cols(self.width, z);
+        let transposed = z % 2 == 1;
+
+        // work in the coordinates of the pass, where the known lines are above and below
+        let (y, x, height, width) = if transposed {
+            (c, r, cols, rows)
+        } else {
+            (r, c, rows, cols)
+        };
+        let get = |y: u32, x: u32, chan: P::Channels| {
+            let (r, c) = if transposed { (x, y) } else { (y, x) };
+            self.get_val(fr, c << col_shift(z), r << row_shift(z), chan)
+        };
+
+        let top = get(y - 1, x, chan);
+        let bottom = if y + 1 < height {
+            get(y + 1, x, chan)
+        } else {
+            top
+        };
+        let left = if x > 0 { get(y, x - 1, chan) } else { top };
+        let top_left = if x > 0 { get(y - 1, x - 1, chan) } else { top };
+        let top_right = if x + 1 < width {
+            get(y - 1, x + 1, chan)
+        } else {
+            top
+        };
+        let bottom_left = if x > 0 && y + 1 < height {
+            get(y + 1, x - 1, chan)
+        } else {
+            left
+        };
+        let bottom_right = if x + 1 < width && y + 1 < height {
+            get(y + 1, x + 1, chan)
+        } else {
+            bottom
+        };
+
+        let luma_miss = match chan.as_channel() {
+            RgbaChannels::Green | RgbaChannels::Blue => {
+                let luma = P::maniac_init_order().as_ref()[0];
+                let below = if y + 1 < height { y + 1 } else { y - 1 };
+                Some(get(y, x, luma) - ((get(y - 1, x, luma) + get(below, x, luma)) >> 1))
+            }
+            _ => None,
+        };
+
+        InterlacedPixelVicinity {
+            pixel: *self
+                .data
+                .get_unchecked(self.get_idx(fr, c << col_shift(z), r << row_shift(z))),
+            chan,
+            top,
+            bottom,
+            left,
+            top_left,
+            top_right,
+            bottom_left,
+            bottom_right,
+            top2: if y > 1 {
+                Some(get(y - 2, x, chan))
+            } else {
+                None
+            },
+            left2: if x > 1 {
+                Some(get(y, x - 2, chan))
+            } else {
+                None
+            },
+            luma_miss,
+        }
+    }
+}
+
+fn make_core_guess<P: Pixel>(pix_vic: &CorePixelVicinity<P>) -> ColorValue {
+    let left = pix_vic.left;
+    let top = pix_vic.top;
+    let top_left = pix_vic.top_left;
+
+    median3(left + top - top_left, left, top)
+}
+
+fn make_edge_guess<P>(info: &FlifInfo, vic: &EdgePixelVicinity<P>) -> ColorValue
+where
+    P: Pixel,
+    P::Channels: ChannelsTrait,
+{
+    let transformation = &info.transform;
+
+    let left = if let Some(val) = vic.left {
+        val
+    } else if let Some(val) = vic.top {
+        val
+    } else if info.second_header.alpha_zero
+        && (vic.chan.as_channel() as usize) < 3
+        && vic.pixel.is_alpha_zero()
+    {
+        let chan = vic.chan.as_channel();
+        (transformation.range(chan).min + transformation.range(chan).max) / 2
+    } else {
+        transformation.range(vic.chan.as_channel()).min
+    };
+
+    let top = if let Some(val) = vic.top { val } else { left };
+
+    let top_left = if let Some(val) = vic.top_left {
+        val
+    } else if let Some(val) = vic.top {
+        val
+    } else {
+        left
+    };
+
+    median3(left + top - top_left, left, top)
+}
+
+fn make_interlaced_guess<P: Pixel>(vic: &InterlacedPixelVicinity<P>, predictor: u8) -> ColorValue {
+    let avg = (vic.top + vic.bottom) >> 1;
+    match predictor {
+        0 => avg,
+        1 => median3(
+            avg,
+            vic.left + vic.top - vic.top_left,
+            vic.left + vic.bottom - vic.bottom_left,
+        ),
+        _ => median3(vic.top, vic.bottom, vic.left),
+    }
+}
+
+/// Rounds the residual of a color `value` to a multiple of `step`, which the lossy encoder
+/// codes in place of the value. Alpha is always kept as is.
+pub(crate) fn quantize(
+    channel: RgbaChannels,
+    value: ColorValue,
+    guess: ColorValue,
+    range: ColorRange,
+    step: ColorValue,
+) -> ColorValue {
+    if step <= 1 || channel as usize >= 3 {
+        return value;
+    }
+
+    let residual = value - guess;
+    let rounded = if residual < 0 {
+        -((step / 2 - residual) / step)
+    } else {
+        (residual + step / 2) / step
+    };
+    // earlier planes are lossy too, so the value might not fit their range anymore
+    (guess + rounded * step).clamp(range.min, range.max)
+}
+
+/// Most bytes the range coder reads for `decisions` binary decisions, it takes in up to two
+/// bytes after each one
+fn coded_bytes(decisions: usize) -> usize {
+    2 * decisions
+}
+
+/// Most binary decisions a uniform value between `0` and `max` takes
+fn val_decisions(max: usize) -> usize {
+    (usize::BITS - max.leading_zeros()) as usize
+}
+
+/// Most binary decisions a value of `range` takes when coded close to zero: one for zero, one
+/// for the sign and an exponent and a mantissa bit for every bit of the range
+fn near_zero_decisions(range: ColorRange) -> usize {
+    let bits = 32 - ((range.max - range.min) as u32).leading_zeros();
+    2 + 2 * bits as usize
+}
+
+/// Number of zoomlevels needed to reduce the image to a single pixel. Zoomlevel 0 is the
+/// full image, odd zoomlevels halve the rows and even zoomlevels halve both dimensions.
+pub(crate) fn zoom_count(width: u32, height: u32) -> usize {
+    let mut z = 0;
+    while (1u64 << row_shift(z)) < u64::from(height) || (1u64 << col_shift(z)) < u64::from(width) {
+        z += 1;
+    }
+    z
+}
+
+fn row_shift(z: usize) -> u32 {
+    (z as u32).div_ceil(2)
+}
+
+fn col_shift(z: usize) -> u32 {
+    z as u32 / 2
+}
+
+pub(crate) fn zoom_rows(height: u32, z: usize) -> u32 {
+    1 + (height - 1).checked_shr(row_shift(z)).unwrap_or(0)
+}
+
+pub(crate) fn zoom_cols(width: u32, z: usize) -> u32 {
+    1 + (width - 1).checked_shr(col_shift(z)).unwrap_or(0)
+}
+
+/// The default order in which planes and zoomlevels are interlaced. Alpha leads, luma
+/// follows it closely and the chroma planes are allowed to lag a few zoomlevels behind.
+fn plane_zoomlevel_order<T: Transform, C: ChannelsTrait>(
+    transform: &T,
+    planes: &[C],
+    begin_zl: usize,
+    end_zl: usize,
+) -> Vec<(usize, usize)> {
+    let mut max_behind = [0, 2, 4, 0, 0];
+    let luma = transform.range(RgbaChannels::Red);
+    if luma.min >= luma.max {
+        max_behind[1] = 0;
+        max_behind[2] = 1;
+    }
+
+    let highest_priority = match planes.len() {
+        5 => 4,
+        4 => 3,
+        _ => 0,
+    };
+    let steps = planes.len() * (begin_zl + 1).saturating_sub(end_zl);
+    let mut zoomlevels = vec![begin_zl + 1; planes.len()];
+    let mut order = Vec::with_capacity(steps);
+
+    let mut next = highest_priority;
+    for step in 0..steps {
+        zoomlevels[next] -= 1;
+        order.push((next, zoomlevels[next]));
+        if step + 1 == steps {
+            break;
+        }
+
+        // the last plane that lags too far behind goes next, like in the reference decoder
+        next = (0..planes.len())
+            .rev()
+            .find(|&p| zoomlevels[p] > zoomlevels[highest_priority] + max_behind[p])
+            .unwrap_or(highest_priority);
+        while zoomlevels[next] <= end_zl {
+            next = (next + 1) % planes.len();
+        }
+    }
+
+    order
+}
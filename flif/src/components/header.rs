@@ -1,13 +1,14 @@
-use std::io::Read;
+use std::io::{Read, Write};
 
 use super::transformations;
 use super::transformations::{Transform, Transformation};
+use crate::coding_image;
 use crate::error::*;
 use crate::numbers::chances::UpdateTable;
-use crate::numbers::rac::RacRead;
-use crate::numbers::symbol::UniformSymbolCoder;
-use crate::numbers::FlifReadExt;
-use crate::pixels::ColorSpace;
+use crate::numbers::rac::{RacRead, RacWrite};
+use crate::numbers::symbol::{UniformSymbolCoder, UniformSymbolWriter};
+use crate::numbers::{FlifReadExt, FlifWriteExt};
+use crate::pixels::{ColorSpace, Rgba, RgbaChannels};
 use crate::Limits;
 
 #[derive(Eq, PartialEq, Debug, Clone, Copy)]
@@ -54,6 +55,12 @@ fn check_limit(width: u32, height: u32, frames: u32, limit: u64) -> Result<()> {
 }
 
 impl Header {
+    /// Zoomlevel at which an interlaced image is reduced to a single pixel, zoomlevel 0 is the
+    /// full image
+    pub fn max_zoomlevel(&self) -> usize {
+        coding_image::zoom_count(self.width, self.height)
+    }
+
     pub(crate) fn from_reader<R: Read>(mut reader: R, limits: &Limits) -> Result<Self> {
         // first read in some magic
         let mut magic_buf = [0; 4];
@@ -114,6 +121,33 @@ impl Header {
             num_frames,
         })
     }
+
+    pub(crate) fn to_writer<W: Write>(self, mut writer: W) -> Result<()> {
+        if self.width == 0 || self.height == 0 || self.num_frames == 0 {
+            Err(Error::InvalidOperation(
+                "an image needs at least one pixel and one frame".into(),
+            ))?;
+        }
+
+        writer.write_all(b"FLIF")?;
+
+        let animated = self.num_frames != 1;
+        let kind = match (self.interlaced, animated) {
+            (false, false) => 3,
+            (true, false) => 4,
+            (false, true) => 5,
+            (true, true) => 6,
+        };
+        writer.write_u8(kind << 4 | self.channels as u8)?;
+        writer.write_u8(b'0' + self.bytes_per_channel as u8)?;
+        writer.write_varint(self.width - 1)?;
+        writer.write_varint(self.height - 1)?;
+        if animated {
+            writer.write_varint(self.num_frames - 2)?;
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -127,6 +161,8 @@ pub struct SecondHeader {
     pub alpha_divisor: u8,
     pub custom_bitchance: bool,
     pub transformations: Vec<Transformation>, // Placeholder until transformations are implemented
+    /// Interlaced predictor used for the color of invisible pixels, only present for
+    /// interlaced images with `alpha_zero` set
     pub invis_pixel_predictor: Option<u8>,
 }
 
@@ -134,7 +170,7 @@ impl SecondHeader {
     pub(crate) fn from_rac<R: RacRead>(
         main_header: &Header,
         rac: &mut R,
-    ) -> Result<(Self, Box<Transform>)> {
+    ) -> Result<(Self, Box<dyn Transform>)> {
         let bits_per_pixel = (0..main_header.channels as u8)
             .map(|_| match main_header.bytes_per_channel {
                 BytesPerChannel::One => Ok(8),
@@ -177,15 +213,29 @@ impl SecondHeader {
         } else {
             (2, 19, false)
         };
+
+        if custom_bitchance {
+            Err(Error::Unimplemented(
+                "Custom bitchances are currently unimplemented in the FLIF standard.",
+            ))?;
+        }
         let update_table = UpdateTable::new(alpha_divisor, cutoff);
 
-        let (transformations, transform) =
-            transformations::load_transformations(rac, main_header.channels, &update_table)?;
+        let (transformations, transform) = transformations::load_transformations(
+            rac,
+            main_header,
+            &bits_per_pixel,
+            alpha_zero,
+            &update_table,
+        )?;
 
-        let invis_pixel_predictor = if alpha_zero && main_header.interlaced {
+        // only interlaced images that can contain invisible pixels store their predictor
+        let invis_pixel_predictor = if alpha_zero
+            && main_header.interlaced
+            && transform.range(RgbaChannels::Alpha).min <= 0
+        {
             Some(rac.read_val(0, 2)?)
         } else {
-            // Garbage value
             None
         };
 
@@ -205,4 +255,68 @@ impl SecondHeader {
             transform,
         ))
     }
+
+    /// Writes the header the way `from_rac` reads it. The transformations are picked from
+    /// `permitted` to suit `pixels`, which get transformed, and are stored in `transformations`.
+    pub(crate) fn write_rac<W: RacWrite>(
+        &mut self,
+        main_header: &Header,
+        rac: &mut W,
+        pixels: &mut [Rgba],
+        permitted: &[Transformation],
+    ) -> Result<Box<dyn Transform>> {
+        if main_header.bytes_per_channel == BytesPerChannel::Custom {
+            for &bits in &self.bits_per_pixel {
+                rac.write_val(1, 16, bits)?;
+            }
+        }
+
+        if main_header.channels == ColorSpace::RGBA {
+            rac.write_bool(self.alpha_zero)?;
+        }
+
+        if main_header.num_frames != 1 {
+            rac.write_val(0, 100, self.loops.unwrap_or(0))?;
+            let delays = self.frame_delay.as_ref().map_or(&[][..], |delays| delays);
+            for fr in 0..main_header.num_frames as usize {
+                rac.write_val(0, 60_000, delays.get(fr).cloned().unwrap_or(0))?;
+            }
+        }
+
+        rac.write_bool(self.custom_cutoff)?;
+        if self.custom_cutoff {
+            rac.write_val(1, 128, self.cutoff)?;
+            rac.write_val(2, 128, self.alpha_divisor)?;
+            rac.write_bool(self.custom_bitchance)?;
+        }
+        if self.custom_bitchance {
+            Err(Error::Unimplemented(
+                "Custom bitchances are currently unimplemented in the FLIF standard.",
+            ))?;
+        }
+        let update_table = UpdateTable::new(self.alpha_divisor, self.cutoff);
+
+        let (transformations, transform) = transformations::write_transformations(
+            rac,
+            main_header,
+            &self.bits_per_pixel,
+            pixels,
+            permitted,
+            &update_table,
+        )?;
+        self.transformations = transformations;
+
+        self.invis_pixel_predictor = if self.alpha_zero
+            && main_header.interlaced
+            && transform.range(RgbaChannels::Alpha).min <= 0
+        {
+            let predictor = self.invis_pixel_predictor.unwrap_or(0);
+            rac.write_val(0, 2, predictor)?;
+            Some(predictor)
+        } else {
+            None
+        };
+
+        Ok(transform)
+    }
 }
@@ -1,9 +1,10 @@
-use std::io::Read;
+use std::io::{Read, Write};
 
 use crate::error::*;
-use crate::numbers::FlifReadExt;
+use crate::numbers::{FlifReadExt, FlifWriteExt};
 use crate::Limits;
 
+use deflate::deflate_bytes;
 use inflate::inflate_bytes;
 
 #[derive(Copy, Clone, Debug)]
@@ -14,6 +15,18 @@ pub enum ChunkType {
     Unknown([u8; 4]),
 }
 
+impl ChunkType {
+    /// The four bytes naming the chunk in a file
+    pub fn name(self) -> [u8; 4] {
+        match self {
+            ChunkType::Iccp => *b"iCCP",
+            ChunkType::Exif => *b"eXif",
+            ChunkType::Exmp => *b"eXmp",
+            ChunkType::Unknown(name) => name,
+        }
+    }
+}
+
 enum MetadataType {
     Optional(Metadata),
     Required(u8),
@@ -57,7 +70,7 @@ impl Metadata {
         header_buf[0] = reader.read_u8()?;
         match header_buf[0] {
             0 => return Ok(MetadataType::Required(0)),
-            byte @ 1...31 => return Err(Error::UnknownRequiredMetadata(byte)),
+            byte @ 1..=31 => return Err(Error::UnknownRequiredMetadata(byte)),
             _ => {}
         }
 
@@ -86,4 +99,28 @@ impl Metadata {
             content: inflated_chunk,
         }))
     }
+
+    /// Writes every metadata chunk followed by the zero byte that ends the metadata, the
+    /// counterpart of `all_from_reader`
+    pub(crate) fn all_to_writer<W: Write>(metadata: &[Metadata], mut writer: W) -> Result<()> {
+        for chunk in metadata {
+            chunk.to_writer(&mut writer)?;
+        }
+
+        writer.write_u8(0)
+    }
+
+    fn to_writer<W: Write>(&self, mut writer: W) -> Result<()> {
+        let name = self.chunk_type.name();
+        // only optional chunks can be written, they start with a lowercase letter
+        if !name[0].is_ascii_lowercase() {
+            Err(Error::UnknownCriticalMetadata(name))?;
+        }
+
+        let deflated_chunk = deflate_bytes(&self.content);
+        writer.write_all(&name)?;
+        writer.write_varint(deflated_chunk.len())?;
+        writer.write_all(&deflated_chunk)?;
+        Ok(())
+    }
 }
@@ -2,20 +2,20 @@ use super::Transform;
 use crate::components::transformations::ColorRange;
 use crate::error::*;
 use crate::numbers::chances::{ChanceTable, UpdateTable};
-use crate::numbers::near_zero::NearZeroCoder;
-use crate::numbers::rac::RacRead;
+use crate::numbers::near_zero::{NearZeroCoder, NearZeroWriter};
+use crate::numbers::rac::{RacRead, RacWrite};
 use crate::pixels::{ColorSpace, Rgba, RgbaChannels};
 
 #[derive(Debug)]
 pub struct Bounds {
     ranges: [ColorRange; 4],
-    previous_transformation: Box<Transform>,
+    previous_transformation: Box<dyn Transform>,
 }
 
 impl Bounds {
     pub fn new<R: RacRead>(
         rac: &mut R,
-        trans: Box<Transform>,
+        trans: Box<dyn Transform>,
         channels: ColorSpace,
         update_table: &UpdateTable,
     ) -> Result<Bounds> {
@@ -37,6 +37,54 @@ impl Bounds {
             previous_transformation: trans,
         })
     }
+
+    /// Bounds every channel to the values that occur in `pixels`
+    pub fn from_pixels(pixels: &[Rgba], trans: Box<dyn Transform>, channels: ColorSpace) -> Bounds {
+        let mut ranges = [ColorRange::default(); 4];
+        for &c in &RgbaChannels::ORDER[..channels as usize] {
+            let c = c as usize;
+            let values = pixels.iter().map(|pixel| pixel.0[c]);
+            ranges[c] = ColorRange {
+                min: values.clone().min().unwrap_or_default(),
+                max: values.max().unwrap_or_default(),
+            };
+        }
+
+        Bounds {
+            ranges,
+            previous_transformation: trans,
+        }
+    }
+
+    /// Drops the bounds again, returning the transformation they were created on
+    pub fn into_previous(self) -> Box<dyn Transform> {
+        self.previous_transformation
+    }
+
+    /// Whether any channel is bounded tighter than by the previous transformation
+    pub fn is_tighter(&self, channels: ColorSpace) -> bool {
+        RgbaChannels::ORDER[..channels as usize]
+            .iter()
+            .any(|&c| self.ranges[c as usize] != self.previous_transformation.range(c))
+    }
+
+    /// Writes the bounds the way `new` reads them
+    pub fn write<W: RacWrite>(
+        &self,
+        rac: &mut W,
+        channels: ColorSpace,
+        update_table: &UpdateTable,
+    ) -> Result<()> {
+        let mut context = ChanceTable::new(update_table);
+        for &c in &RgbaChannels::ORDER[..channels as usize] {
+            let t_range = self.previous_transformation.range(c);
+            let range = self.ranges[c as usize];
+            rac.write_near_zero(t_range.min, t_range.max, range.min, &mut context)?;
+            rac.write_near_zero(range.min, t_range.max, range.max, &mut context)?;
+        }
+
+        Ok(())
+    }
 }
 
 impl Transform for Bounds {
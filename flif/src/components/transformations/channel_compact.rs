@@ -2,14 +2,14 @@ use super::Transform;
 use crate::components::transformations::ColorRange;
 use crate::error::*;
 use crate::numbers::chances::{ChanceTable, UpdateTable};
-use crate::numbers::near_zero::NearZeroCoder;
-use crate::numbers::rac::RacRead;
-use crate::pixels::{ColorSpace, Rgba, RgbaChannels};
+use crate::numbers::near_zero::{NearZeroCoder, NearZeroWriter};
+use crate::numbers::rac::{RacRead, RacWrite};
+use crate::pixels::{ColorSpace, ColorValue, Rgba, RgbaChannels};
 
 #[derive(Debug)]
 pub struct ChannelCompact {
     ranges: [ColorRange; 4],
-    decompacted: [Vec<i16>; 4],
+    decompacted: [Vec<ColorValue>; 4],
     channels: ColorSpace,
 }
 impl ChannelCompact {
@@ -45,13 +45,71 @@ impl ChannelCompact {
 
         Ok(t)
     }
+
+    /// Compacts every channel to the values that occur in `pixels`
+    pub fn from_pixels(pixels: &[Rgba], channels: ColorSpace) -> ChannelCompact {
+        let mut t = ChannelCompact {
+            ranges: Default::default(),
+            decompacted: Default::default(),
+            channels,
+        };
+
+        for &c in &RgbaChannels::ORDER[..channels as usize] {
+            let c = c as usize;
+            let mut values: Vec<ColorValue> = pixels.iter().map(|pixel| pixel.0[c]).collect();
+            values.sort_unstable();
+            values.dedup();
+
+            t.ranges[c].max = values.len() as ColorValue - 1;
+            t.decompacted[c] = values;
+        }
+
+        t
+    }
+
+    /// Writes the compacted values the way `new` reads them
+    pub fn write<W: RacWrite, T: Transform>(
+        &self,
+        rac: &mut W,
+        transformation: &T,
+        update_table: &UpdateTable,
+    ) -> Result<()> {
+        let mut context = ChanceTable::new(update_table);
+        for &c in &RgbaChannels::ORDER[..self.channels as usize] {
+            let t_range = transformation.range(c);
+            let c = c as usize;
+            let count = self.ranges[c].max;
+            rac.write_near_zero(0, t_range.max - t_range.min, count, &mut context)?;
+            let mut min = t_range.min;
+            for (i, &value) in self.decompacted[c].iter().enumerate() {
+                let max = t_range.max - (min + (count - i as ColorValue));
+                rac.write_near_zero(0, max, value - min, &mut context)?;
+                min = value + 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn forward(&self, mut pixel: Rgba) -> Rgba {
+        for &c in &RgbaChannels::ORDER[..self.channels as usize] {
+            let c = c as usize;
+            // every value of the image is present, so the search can't fail
+            let index = self.decompacted[c].binary_search(&pixel.0[c]).unwrap_or(0);
+            pixel.0[c] = index as ColorValue;
+        }
+
+        pixel
+    }
 }
 
 impl Transform for ChannelCompact {
     fn undo(&self, mut pixel: Rgba) -> Rgba {
         for &c in &RgbaChannels::ORDER[..self.channels as usize] {
             let c = c as usize;
-            pixel.0[c] = self.decompacted[c][pixel.0[c] as usize];
+            // invisible pixels can end up outside of the compacted range, they are clamped into it
+            let index = (pixel.0[c].max(0) as usize).min(self.decompacted[c].len() - 1);
+            pixel.0[c] = self.decompacted[c][index];
         }
 
         pixel
@@ -0,0 +1,329 @@
+use std::cmp;
+use std::collections::HashMap;
+
+use super::Transform;
+use crate::components::transformations::ColorRange;
+use crate::error::*;
+use crate::numbers::chances::{ChanceTable, UpdateTable};
+use crate::numbers::near_zero::NearZeroCoder;
+use crate::numbers::rac::RacRead;
+use crate::pixels::{ColorSpace, ColorValue, Rgba, RgbaChannels};
+
+/// Maximum number of discrete values per bucket for each plane
+const MAX_PER_BUCKET: [ColorValue; 4] = [255, 510, 5, 255];
+
+/// Number of co values sharing a bucket of the cg plane
+const CO_PER_BUCKET: ColorValue = 4;
+
+#[derive(Debug, Clone)]
+struct ColorBucket {
+    min: ColorValue,
+    max: ColorValue,
+    values: Vec<ColorValue>,
+    discrete: bool,
+}
+
+impl ColorBucket {
+    fn empty() -> ColorBucket {
+        ColorBucket {
+            min: ColorValue::MAX,
+            max: ColorValue::MIN,
+            values: Vec::new(),
+            discrete: false,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.min > self.max
+    }
+
+    fn contains(&self, value: ColorValue) -> bool {
+        if value < self.min || value > self.max {
+            return false;
+        }
+
+        !self.discrete || self.values.binary_search(&value).is_ok()
+    }
+
+    fn snap(&self, value: ColorValue) -> ColorValue {
+        if value <= self.min {
+            return self.min;
+        }
+        if value >= self.max {
+            return self.max;
+        }
+        if !self.discrete {
+            return value;
+        }
+
+        // pick the closest value, preferring the lower one on ties
+        match self.values.binary_search(&value) {
+            Ok(_) => value,
+            Err(i) => {
+                let (lower, upper) = (self.values[i - 1], self.values[i]);
+                if value - lower <= upper - value {
+                    lower
+                } else {
+                    upper
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ColorBuckets {
+    bucket0: ColorBucket,
+    bucket1: Vec<ColorBucket>,
+    /// buckets of the third plane by luma and chroma bucket, only the ones that are coded
+    bucket2: HashMap<(usize, usize), ColorBucket>,
+    bucket3: ColorBucket,
+    min0: ColorValue,
+    min1: ColorValue,
+    previous_transformation: Box<dyn Transform>,
+}
+
+impl ColorBuckets {
+    pub fn new<R: RacRead>(
+        rac: &mut R,
+        trans: Box<dyn Transform>,
+        channels: ColorSpace,
+        update_table: &UpdateTable,
+    ) -> Result<ColorBuckets> {
+        if channels == ColorSpace::Monochrome {
+            return Err(Error::InvalidOperation(
+                "color bucket transformation requires a color image".into(),
+            ));
+        }
+
+        let range0 = trans.range(RgbaChannels::Red);
+        let range1 = trans.range(RgbaChannels::Green);
+        let range2 = trans.range(RgbaChannels::Blue);
+        let size0 = (range0.max - range0.min + 1) as usize;
+        let size1 = ((range1.max - range1.min) / CO_PER_BUCKET + 1) as usize;
+        let mut t = ColorBuckets {
+            bucket0: ColorBucket::empty(),
+            bucket1: vec![ColorBucket::empty(); size0],
+            bucket2: HashMap::new(),
+            bucket3: ColorBucket::empty(),
+            min0: range0.min,
+            min1: range1.min,
+            previous_transformation: trans,
+        };
+
+        let mut context = ChanceTable::new(update_table);
+        let mut lower = [0; 3];
+        let mut upper = [0; 3];
+        t.bucket0 = t.load_bucket(rac, &mut context, 0, lower, upper)?;
+
+        for y in 0..size0 {
+            lower[0] = t.min0 + y as ColorValue;
+            upper[0] = lower[0];
+            t.bucket1[y] = t.load_bucket(rac, &mut context, 1, lower, upper)?;
+        }
+
+        if range2.min < range2.max {
+            for y in 0..size0 {
+                // none of the buckets of a luma without chroma are coded
+                if t.bucket1[y].is_empty() {
+                    continue;
+                }
+
+                lower[0] = t.min0 + y as ColorValue;
+                upper[0] = lower[0];
+                for co in 0..size1 {
+                    lower[1] = t.min1 + co as ColorValue * CO_PER_BUCKET;
+                    upper[1] = lower[1] + CO_PER_BUCKET - 1;
+                    let bucket = t.load_bucket(rac, &mut context, 2, lower, upper)?;
+                    if !bucket.is_empty() {
+                        t.bucket2.insert((y, co), bucket);
+                    }
+                }
+            }
+        }
+
+        if channels == ColorSpace::RGBA {
+            t.bucket3 = t.load_bucket(rac, &mut context, 3, lower, upper)?;
+        }
+
+        Ok(t)
+    }
+
+    fn load_bucket<R: RacRead>(
+        &self,
+        rac: &mut R,
+        context: &mut ChanceTable,
+        plane: usize,
+        lower: [ColorValue; 3],
+        upper: [ColorValue; 3],
+    ) -> Result<ColorBucket> {
+        let mut bucket = ColorBucket::empty();
+
+        // a bucket is only coded if its previous planes can actually occur
+        if plane < 3 && !(0..plane).all(|p| self.exists_between(p, lower, upper)) {
+            return Ok(bucket);
+        }
+
+        if rac.read_near_zero(0, 1, context)? == 0 {
+            return Ok(bucket);
+        }
+
+        let range = self.source_range(plane, lower, upper);
+        if range.min == range.max {
+            bucket.min = range.min;
+            bucket.max = range.min;
+            return Ok(bucket);
+        }
+
+        bucket.min = rac.read_near_zero(range.min, range.max, context)?;
+        bucket.max = rac.read_near_zero(bucket.min, range.max, context)?;
+        if bucket.max - bucket.min < 2 {
+            return Ok(bucket);
+        }
+
+        bucket.discrete = rac.read_near_zero(0, 1, context)? == 1;
+        if bucket.discrete {
+            let count = rac.read_near_zero(
+                2,
+                cmp::min(MAX_PER_BUCKET[plane], bucket.max - bucket.min),
+                context,
+            )?;
+            bucket.values.push(bucket.min);
+            let mut value = bucket.min;
+            for i in 1..count - 1 {
+                value = rac.read_near_zero(value + 1, bucket.max + 1 - count + i, context)?;
+                bucket.values.push(value);
+            }
+            bucket.values.push(bucket.max);
+        }
+
+        Ok(bucket)
+    }
+
+    /// Union of the ranges the previous transformation allows for `plane` over every
+    /// combination of previous plane values between `lower` and `upper`
+    fn source_range(
+        &self,
+        plane: usize,
+        lower: [ColorValue; 3],
+        upper: [ColorValue; 3],
+    ) -> ColorRange {
+        let trans = &self.previous_transformation;
+        let channel = RgbaChannels::ORDER[plane];
+        if plane == 0 || plane == 3 {
+            return trans.range(channel);
+        }
+
+        let mut range = ColorRange {
+            min: ColorValue::MAX,
+            max: ColorValue::MIN,
+        };
+        let (co_min, co_max) = if plane == 2 {
+            (lower[1], upper[1])
+        } else {
+            (0, 0)
+        };
+        for y in lower[0]..=upper[0] {
+            for co in co_min..=co_max {
+                let crange = trans.crange(channel, Rgba([y, co, 0, 0]));
+                range.min = cmp::min(range.min, crange.min);
+                range.max = cmp::max(range.max, crange.max);
+            }
+        }
+
+        range
+    }
+
+    /// Checks if any value between `lower[plane]` and `upper[plane]` is present in the buckets
+    fn exists_between(&self, plane: usize, lower: [ColorValue; 3], upper: [ColorValue; 3]) -> bool {
+        (lower[plane]..=upper[plane]).any(|value| {
+            let mut pixel = [lower[0], lower[1], 0, 0];
+            pixel[plane] = value;
+            self.bucket(RgbaChannels::ORDER[plane], &pixel)
+                .is_some_and(|bucket| bucket.contains(value))
+        })
+    }
+
+    fn bucket(&self, channel: RgbaChannels, values: &[ColorValue; 4]) -> Option<&ColorBucket> {
+        let y = (values[0] - self.min0) as usize;
+        match channel {
+            RgbaChannels::Red => Some(&self.bucket0),
+            RgbaChannels::Green => self.bucket1.get(y),
+            RgbaChannels::Blue => {
+                let co = ((values[1] - self.min1) / CO_PER_BUCKET) as usize;
+                self.bucket2.get(&(y, co))
+            }
+            RgbaChannels::Alpha => Some(&self.bucket3),
+            RgbaChannels::Lookback => None,
+        }
+        .filter(|bucket| !bucket.is_empty())
+    }
+}
+
+impl Transform for ColorBuckets {
+    fn snap(&self, channel: RgbaChannels, pixel: Rgba, value: ColorValue) -> ColorValue {
+        match self.bucket(channel, &pixel.0) {
+            Some(bucket) => bucket.snap(value),
+            None => self.previous_transformation.snap(channel, pixel, value),
+        }
+    }
+
+    fn undo(&self, pixel: Rgba) -> Rgba {
+        self.previous_transformation.undo(pixel)
+    }
+
+    fn range(&self, channel: RgbaChannels) -> ColorRange {
+        self.previous_transformation.range(channel)
+    }
+
+    fn crange(&self, channel: RgbaChannels, values: Rgba) -> ColorRange {
+        match self.bucket(channel, &values.0) {
+            Some(bucket) => ColorRange {
+                min: bucket.min,
+                max: bucket.max,
+            },
+            None => self.previous_transformation.crange(channel, values),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ColorBuckets;
+    use crate::components::transformations::{ColorRange, Orig, Transform};
+    use crate::numbers::chances::{ChanceTable, UpdateTable};
+    use crate::numbers::near_zero::NearZeroWriter;
+    use crate::numbers::rac::{Rac, RacWrite};
+    use crate::pixels::{ColorSpace, Rgba, RgbaChannels};
+
+    #[test]
+    fn test_sixteen_bit_buckets() {
+        // a single color, every plane has one bucket holding just its value
+        let update_table = UpdateTable::new(19, 2);
+        let mut buf = Vec::new();
+        {
+            let mut rac = Rac::from_writer(&mut buf);
+            let mut context = ChanceTable::new(&update_table);
+            for &value in &[100, 200, 300] {
+                rac.write_near_zero(0, 1, 1, &mut context).unwrap();
+                rac.write_near_zero(0, 65535, value, &mut context).unwrap();
+                rac.write_near_zero(value, 65535, value, &mut context)
+                    .unwrap();
+            }
+            rac.flush().unwrap();
+        }
+
+        let mut rac = Rac::from_reader(buf.as_slice()).unwrap();
+        let orig = Box::new(Orig::new(&[16, 16, 16]));
+        let buckets = ColorBuckets::new(&mut rac, orig, ColorSpace::RGB, &update_table).unwrap();
+
+        let color = Rgba([100, 200, 0, 0]);
+        let single = |value| ColorRange {
+            min: value,
+            max: value,
+        };
+        assert_eq!(single(100), buckets.crange(RgbaChannels::Red, color));
+        assert_eq!(single(200), buckets.crange(RgbaChannels::Green, color));
+        assert_eq!(single(300), buckets.crange(RgbaChannels::Blue, color));
+    }
+}
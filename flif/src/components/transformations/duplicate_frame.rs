@@ -0,0 +1,114 @@
+use super::Transform;
+use crate::components::header::Header;
+use crate::components::transformations::ColorRange;
+use crate::error::*;
+use crate::numbers::chances::{ChanceTable, UpdateTable};
+use crate::numbers::near_zero::{NearZeroCoder, NearZeroWriter};
+use crate::numbers::rac::{RacRead, RacWrite};
+use crate::pixels::{ColorValue, Rgba, RgbaChannels};
+
+#[derive(Debug)]
+pub struct DuplicateFrame {
+    seen_before: Vec<Option<usize>>,
+    previous_transformation: Box<dyn Transform>,
+}
+
+impl DuplicateFrame {
+    pub fn new<R: RacRead>(
+        rac: &mut R,
+        trans: Box<dyn Transform>,
+        header: &Header,
+        update_table: &UpdateTable,
+    ) -> Result<DuplicateFrame> {
+        let mut context = ChanceTable::new(update_table);
+        let mut seen_before = vec![None];
+        for frame in 1..header.num_frames as ColorValue {
+            let original = rac.read_near_zero(-1, frame - 1, &mut context)?;
+            seen_before.push(if original < 0 {
+                None
+            } else {
+                Some(original as usize)
+            });
+        }
+
+        Ok(DuplicateFrame {
+            seen_before,
+            previous_transformation: trans,
+        })
+    }
+
+    /// Marks every frame of `pixels` that repeats an earlier frame
+    pub fn from_pixels(
+        pixels: &[Rgba],
+        trans: Box<dyn Transform>,
+        header: &Header,
+    ) -> DuplicateFrame {
+        let frame_size = (header.width * header.height) as usize;
+        let frames = pixels.chunks(frame_size).collect::<Vec<_>>();
+        let seen_before = frames
+            .iter()
+            .enumerate()
+            .map(|(fr, frame)| {
+                frames[..fr]
+                    .iter()
+                    .position(|earlier| earlier.iter().zip(frame.iter()).all(|(a, b)| a.0 == b.0))
+            })
+            .collect();
+
+        DuplicateFrame {
+            seen_before,
+            previous_transformation: trans,
+        }
+    }
+
+    /// Drops the duplicates again, returning the transformation they were created on
+    pub fn into_previous(self) -> Box<dyn Transform> {
+        self.previous_transformation
+    }
+
+    /// Whether any frame repeats an earlier one
+    pub fn has_duplicates(&self) -> bool {
+        self.seen_before.iter().any(Option::is_some)
+    }
+
+    /// Writes the duplicates the way `new` reads them
+    pub fn write<W: RacWrite>(&self, rac: &mut W, update_table: &UpdateTable) -> Result<()> {
+        let mut context = ChanceTable::new(update_table);
+        for (frame, original) in self.seen_before.iter().enumerate().skip(1) {
+            let original = original.map_or(-1, |original| original as ColorValue);
+            rac.write_near_zero(-1, frame as ColorValue - 1, original, &mut context)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Transform for DuplicateFrame {
+    fn snap(&self, channel: RgbaChannels, pixel: Rgba, value: ColorValue) -> ColorValue {
+        self.previous_transformation.snap(channel, pixel, value)
+    }
+
+    fn undo(&self, pixel: Rgba) -> Rgba {
+        self.previous_transformation.undo(pixel)
+    }
+
+    fn range(&self, channel: RgbaChannels) -> ColorRange {
+        self.previous_transformation.range(channel)
+    }
+
+    fn crange(&self, channel: RgbaChannels, values: Rgba) -> ColorRange {
+        self.previous_transformation.crange(channel, values)
+    }
+
+    fn seen_before(&self, frame: usize) -> Option<usize> {
+        self.seen_before[frame]
+    }
+
+    fn frame_shape(&self, frame: usize, row: u32) -> Option<(u32, u32)> {
+        self.previous_transformation.frame_shape(frame, row)
+    }
+
+    fn max_lookback(&self) -> Option<ColorValue> {
+        self.previous_transformation.max_lookback()
+    }
+}
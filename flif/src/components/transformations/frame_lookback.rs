@@ -0,0 +1,88 @@
+use super::Transform;
+use crate::components::header::Header;
+use crate::components::transformations::ColorRange;
+use crate::error::*;
+use crate::numbers::chances::{ChanceTable, UpdateTable};
+use crate::numbers::near_zero::NearZeroCoder;
+use crate::numbers::rac::RacRead;
+use crate::pixels::{ColorSpace, ColorValue, Rgba, RgbaChannels};
+
+/// Value of the alpha plane added to images without alpha
+const OPAQUE: ColorRange = ColorRange { min: 255, max: 255 };
+
+#[derive(Debug)]
+pub struct FrameLookback {
+    max_lookback: ColorValue,
+    has_alpha: bool,
+    previous_transformation: Box<dyn Transform>,
+}
+
+impl FrameLookback {
+    pub fn new<R: RacRead>(
+        rac: &mut R,
+        trans: Box<dyn Transform>,
+        header: &Header,
+        update_table: &UpdateTable,
+    ) -> Result<FrameLookback> {
+        if header.num_frames < 2 {
+            return Err(Error::InvalidOperation(
+                "frame lookback transformation requires an animation".into(),
+            ));
+        }
+
+        let mut context = ChanceTable::new(update_table);
+        let max_lookback =
+            rac.read_near_zero(1, header.num_frames as ColorValue - 1, &mut context)?;
+
+        Ok(FrameLookback {
+            max_lookback,
+            has_alpha: header.channels == ColorSpace::RGBA,
+            previous_transformation: trans,
+        })
+    }
+}
+
+impl Transform for FrameLookback {
+    fn snap(&self, channel: RgbaChannels, pixel: Rgba, value: ColorValue) -> ColorValue {
+        match channel {
+            RgbaChannels::Lookback => value.max(0).min(self.max_lookback),
+            RgbaChannels::Alpha if !self.has_alpha => OPAQUE.min,
+            _ => self.previous_transformation.snap(channel, pixel, value),
+        }
+    }
+
+    fn undo(&self, pixel: Rgba) -> Rgba {
+        self.previous_transformation.undo(pixel)
+    }
+
+    fn range(&self, channel: RgbaChannels) -> ColorRange {
+        match channel {
+            RgbaChannels::Lookback => ColorRange {
+                min: 0,
+                max: self.max_lookback,
+            },
+            RgbaChannels::Alpha if !self.has_alpha => OPAQUE,
+            _ => self.previous_transformation.range(channel),
+        }
+    }
+
+    fn crange(&self, channel: RgbaChannels, values: Rgba) -> ColorRange {
+        match channel {
+            RgbaChannels::Lookback => self.range(channel),
+            RgbaChannels::Alpha if !self.has_alpha => OPAQUE,
+            _ => self.previous_transformation.crange(channel, values),
+        }
+    }
+
+    fn seen_before(&self, frame: usize) -> Option<usize> {
+        self.previous_transformation.seen_before(frame)
+    }
+
+    fn frame_shape(&self, frame: usize, row: u32) -> Option<(u32, u32)> {
+        self.previous_transformation.frame_shape(frame, row)
+    }
+
+    fn max_lookback(&self) -> Option<ColorValue> {
+        Some(self.max_lookback)
+    }
+}
@@ -0,0 +1,166 @@
+use super::Transform;
+use crate::components::header::Header;
+use crate::components::transformations::ColorRange;
+use crate::error::*;
+use crate::numbers::chances::{ChanceTable, UpdateTable};
+use crate::numbers::near_zero::{NearZeroCoder, NearZeroWriter};
+use crate::numbers::rac::{RacRead, RacWrite};
+use crate::pixels::{ColorValue, Rgba, RgbaChannels};
+
+#[derive(Debug)]
+pub struct FrameShape {
+    /// index of the first row of each frame in `begin` and `end`, `None` for frames
+    /// that are coded in full or duplicated
+    offsets: Vec<Option<usize>>,
+    begin: Vec<u32>,
+    end: Vec<u32>,
+    previous_transformation: Box<dyn Transform>,
+}
+
+impl FrameShape {
+    pub fn new<R: RacRead>(
+        rac: &mut R,
+        trans: Box<dyn Transform>,
+        header: &Header,
+        update_table: &UpdateTable,
+    ) -> Result<FrameShape> {
+        let mut offsets = vec![None];
+        let mut rows = 0;
+        for frame in 1..header.num_frames as usize {
+            if trans.seen_before(frame).is_some() {
+                offsets.push(None);
+            } else {
+                offsets.push(Some(rows));
+                rows += header.height as usize;
+            }
+        }
+
+        let mut context = ChanceTable::new(update_table);
+        let cols = header.width;
+        let begin = (0..rows)
+            .map(|_| rac.read_near_zero(0, cols, &mut context))
+            .collect::<Result<Vec<_>>>()?;
+        let end = begin
+            .iter()
+            .map(|&b| Ok(cols - rac.read_near_zero(0, cols - b, &mut context)?))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(FrameShape {
+            offsets,
+            begin,
+            end,
+            previous_transformation: trans,
+        })
+    }
+
+    /// Narrows every row of the frames in `pixels` to the columns that differ from the
+    /// previous frame
+    pub fn from_pixels(pixels: &[Rgba], trans: Box<dyn Transform>, header: &Header) -> FrameShape {
+        let cols = header.width as usize;
+        let frame_size = cols * header.height as usize;
+        let mut offsets = vec![None];
+        let mut begin = Vec::new();
+        let mut end = Vec::new();
+        for frame in 1..header.num_frames as usize {
+            if trans.seen_before(frame).is_some() {
+                offsets.push(None);
+                continue;
+            }
+
+            offsets.push(Some(begin.len()));
+            let previous = &pixels[(frame - 1) * frame_size..frame * frame_size];
+            let current = &pixels[frame * frame_size..(frame + 1) * frame_size];
+            for (previous, current) in previous.chunks(cols).zip(current.chunks(cols)) {
+                let changed = |(a, b): (&Rgba, &Rgba)| a.0 != b.0;
+                let mut pairs = previous.iter().zip(current.iter());
+                match pairs.clone().position(changed) {
+                    Some(first) => {
+                        let last = pairs.rposition(changed).unwrap_or(first);
+                        begin.push(first as u32);
+                        end.push(last as u32 + 1);
+                    }
+                    // unchanged rows are empty
+                    None => {
+                        begin.push(cols as u32);
+                        end.push(cols as u32);
+                    }
+                }
+            }
+        }
+
+        FrameShape {
+            offsets,
+            begin,
+            end,
+            previous_transformation: trans,
+        }
+    }
+
+    /// Drops the frame shapes again, returning the transformation they were created on
+    pub fn into_previous(self) -> Box<dyn Transform> {
+        self.previous_transformation
+    }
+
+    /// Whether any row leaves out some columns of its frame
+    pub fn is_partial(&self, header: &Header) -> bool {
+        self.begin
+            .iter()
+            .zip(self.end.iter())
+            .any(|(&begin, &end)| begin > 0 || end < header.width)
+    }
+
+    /// Writes the frame shapes the way `new` reads them
+    pub fn write<W: RacWrite>(
+        &self,
+        rac: &mut W,
+        header: &Header,
+        update_table: &UpdateTable,
+    ) -> Result<()> {
+        let mut context = ChanceTable::new(update_table);
+        let cols = header.width;
+        for &begin in &self.begin {
+            rac.write_near_zero(0, cols, begin, &mut context)?;
+        }
+        for (&begin, &end) in self.begin.iter().zip(self.end.iter()) {
+            rac.write_near_zero(0, cols - begin, cols - end, &mut context)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Transform for FrameShape {
+    fn snap(&self, channel: RgbaChannels, pixel: Rgba, value: ColorValue) -> ColorValue {
+        self.previous_transformation.snap(channel, pixel, value)
+    }
+
+    fn undo(&self, pixel: Rgba) -> Rgba {
+        self.previous_transformation.undo(pixel)
+    }
+
+    fn range(&self, channel: RgbaChannels) -> ColorRange {
+        self.previous_transformation.range(channel)
+    }
+
+    fn crange(&self, channel: RgbaChannels, values: Rgba) -> ColorRange {
+        self.previous_transformation.crange(channel, values)
+    }
+
+    fn seen_before(&self, frame: usize) -> Option<usize> {
+        self.previous_transformation.seen_before(frame)
+    }
+
+    fn frame_shape(&self, frame: usize, row: u32) -> Option<(u32, u32)> {
+        match self.offsets[frame] {
+            Some(offset) => {
+                let idx = offset + row as usize;
+                Some((self.begin[idx], self.end[idx]))
+            }
+            None => self.previous_transformation.frame_shape(frame, row),
+        }
+    }
+
+    fn max_lookback(&self) -> Option<ColorValue> {
+        self.previous_transformation.max_lookback()
+    }
+}
@@ -1,15 +1,30 @@
+use std::collections::HashSet;
+
 use self::bounds::Bounds;
 use self::channel_compact::ChannelCompact;
+use self::color_buckets::ColorBuckets;
+use self::duplicate_frame::DuplicateFrame;
+use self::frame_lookback::FrameLookback;
+use self::frame_shape::FrameShape;
+use self::palette::Palette;
+use self::palette_alpha::PaletteAlpha;
 use self::permute_planes::PermutePlanes;
 use self::ycocg::YCoGg;
+use crate::components::header::Header;
 use crate::error::*;
 use crate::numbers::chances::UpdateTable;
-use crate::numbers::rac::RacRead;
-use crate::numbers::symbol::UniformSymbolCoder;
+use crate::numbers::rac::{RacRead, RacWrite};
+use crate::numbers::symbol::{UniformSymbolCoder, UniformSymbolWriter};
 use crate::pixels::{ColorSpace, ColorValue, Rgba, RgbaChannels};
 
 mod bounds;
 mod channel_compact;
+mod color_buckets;
+mod duplicate_frame;
+mod frame_lookback;
+mod frame_shape;
+mod palette;
+mod palette_alpha;
 mod permute_planes;
 mod ycocg;
 
@@ -44,6 +59,22 @@ impl Transformation {
             _ => None,
         }
     }
+
+    pub(crate) fn id(self) -> u8 {
+        use self::Transformation::*;
+        match self {
+            ChannelCompact => 0,
+            YCoGg => 1,
+            PermutePlanes => 3,
+            Bounds => 4,
+            PaletteAlpha => 5,
+            Palette => 6,
+            ColorBuckets => 7,
+            DuplicateFrame => 10,
+            FrameShape => 11,
+            FrameLookback => 12,
+        }
+    }
 }
 
 impl ::std::fmt::Display for Transformation {
@@ -82,9 +113,31 @@ pub trait Transform: ::std::fmt::Debug + Send + Sync {
     fn range(&self, channel: RgbaChannels) -> ColorRange;
 
     fn crange(&self, channel: RgbaChannels, values: Rgba) -> ColorRange;
+
+    /// Earlier frame that `frame` is an exact copy of, set by `DuplicateFrame`. Like the other
+    /// animation queries this has to be forwarded by the animation transformations, which
+    /// always come last.
+    fn seen_before(&self, _frame: usize) -> Option<usize> {
+        None
+    }
+
+    /// Columns `begin..end` of a row that are coded, the rest of the row is a copy of the
+    /// previous frame. Set by `FrameShape`, `None` means the whole row is coded.
+    fn frame_shape(&self, _frame: usize, _row: u32) -> Option<(u32, u32)> {
+        None
+    }
+
+    /// Largest number of frames a pixel may look back, set by `FrameLookback`
+    fn max_lookback(&self) -> Option<ColorValue> {
+        None
+    }
 }
 
-impl Transform for Box<Transform> {
+impl Transform for Box<dyn Transform> {
+    fn snap(&self, channel: RgbaChannels, pixel: Rgba, value: ColorValue) -> ColorValue {
+        (**self).snap(channel, pixel, value)
+    }
+
     fn undo(&self, pixel: Rgba) -> Rgba {
         (**self).undo(pixel)
     }
@@ -96,49 +149,105 @@ impl Transform for Box<Transform> {
     fn crange(&self, channel: RgbaChannels, values: Rgba) -> ColorRange {
         (**self).crange(channel, values)
     }
+
+    fn seen_before(&self, frame: usize) -> Option<usize> {
+        (**self).seen_before(frame)
+    }
+
+    fn frame_shape(&self, frame: usize, row: u32) -> Option<(u32, u32)> {
+        (**self).frame_shape(frame, row)
+    }
+
+    fn max_lookback(&self) -> Option<ColorValue> {
+        (**self).max_lookback()
+    }
 }
 
+/// Ranges of the untransformed image, the start of every transformation chain
 #[derive(Debug)]
-struct Orig;
+pub(crate) struct Orig {
+    ranges: [ColorRange; 4],
+}
+
+impl Orig {
+    pub(crate) fn new(bits_per_pixel: &[u8]) -> Orig {
+        // planes the image doesn't have are constant
+        let mut ranges = [ColorRange::default(); 4];
+        for (range, &bits) in ranges.iter_mut().zip(bits_per_pixel) {
+            range.max = (1 << bits) - 1;
+        }
+
+        Orig { ranges }
+    }
+}
 
 impl Transform for Orig {
     fn undo(&self, pixel: Rgba) -> Rgba {
         pixel
     }
 
-    fn range(&self, _channel: RgbaChannels) -> ColorRange {
-        ColorRange { min: 0, max: 255 }
+    fn range(&self, channel: RgbaChannels) -> ColorRange {
+        self.ranges[channel as usize]
     }
 
-    fn crange(&self, _channel: RgbaChannels, _values: Rgba) -> ColorRange {
-        ColorRange { min: 0, max: 255 }
+    fn crange(&self, channel: RgbaChannels, _values: Rgba) -> ColorRange {
+        self.ranges[channel as usize]
     }
 }
 
 pub fn load_transformations<R: RacRead>(
     rac: &mut R,
-    channels: ColorSpace,
+    header: &Header,
+    bits_per_pixel: &[u8],
+    alpha_zero: bool,
     update_table: &UpdateTable,
-) -> Result<(Vec<Transformation>, Box<Transform>)> {
-    let mut transform: Box<Transform> = Box::new(Orig);
+) -> Result<(Vec<Transformation>, Box<dyn Transform>)> {
+    let channels = header.channels;
+    let mut transform: Box<dyn Transform> = Box::new(Orig::new(bits_per_pixel));
     let mut transformations = Vec::new();
     while rac.read_bit()? {
         let id = Transformation::from_id(rac.read_val(0, 13)?).ok_or(Error::InvalidOperation(
             "Invalid transformation identifier read, possibly corrupt file".into(),
         ))?;
+        // the lookback plane is only known to the frame lookback, the transformations below it
+        // would index their ranges with it
+        if transform.max_lookback().is_some() {
+            Err(Error::InvalidOperation(
+                "no transformation may follow the frame lookback".into(),
+            ))?;
+        }
         transform = match id {
             Transformation::ChannelCompact => {
                 Box::new(ChannelCompact::new(rac, transform, channels, update_table)?)
             }
-            Transformation::YCoGg => Box::new(YCoGg::new(transform)) as Box<Transform>,
+            Transformation::YCoGg => Box::new(YCoGg::new(transform)) as Box<dyn Transform>,
             Transformation::PermutePlanes => {
-                Box::new(PermutePlanes::new(transform)) as Box<Transform>
+                Box::new(PermutePlanes::new(rac, transform, channels, update_table)?)
             }
             Transformation::Bounds => {
                 Box::new(Bounds::new(rac, transform, channels, update_table)?)
             }
-            _ => {
-                return Err(Error::UnimplementedTransformation(id.to_string()));
+            Transformation::PaletteAlpha => Box::new(PaletteAlpha::new(
+                rac,
+                transform,
+                channels,
+                alpha_zero,
+                update_table,
+            )?),
+            Transformation::Palette => {
+                Box::new(Palette::new(rac, transform, channels, update_table)?)
+            }
+            Transformation::ColorBuckets => {
+                Box::new(ColorBuckets::new(rac, transform, channels, update_table)?)
+            }
+            Transformation::DuplicateFrame => {
+                Box::new(DuplicateFrame::new(rac, transform, header, update_table)?)
+            }
+            Transformation::FrameShape => {
+                Box::new(FrameShape::new(rac, transform, header, update_table)?)
+            }
+            Transformation::FrameLookback => {
+                Box::new(FrameLookback::new(rac, transform, header, update_table)?)
             }
         };
 
@@ -148,6 +257,140 @@ pub fn load_transformations<R: RacRead>(
     Ok((transformations, transform))
 }
 
+/// Largest palette the encoder creates, coding the palette itself outweighs the gains beyond it
+const MAX_ENCODER_PALETTE_SIZE: usize = 512;
+
+fn write_id<W: RacWrite>(rac: &mut W, id: Transformation) -> Result<()> {
+    rac.write_bit(true)?;
+    rac.write_val(0, 13, id.id())
+}
+
+/// Picks the transformations that suit `pixels` and writes them the way `load_transformations`
+/// reads them. `pixels` holds the pixels of every frame and is transformed along the way.
+/// Only the transformations in `permitted` are considered.
+pub(crate) fn write_transformations<W: RacWrite>(
+    rac: &mut W,
+    header: &Header,
+    bits_per_pixel: &[u8],
+    pixels: &mut [Rgba],
+    permitted: &[Transformation],
+    update_table: &UpdateTable,
+) -> Result<(Vec<Transformation>, Box<dyn Transform>)> {
+    let channels = header.channels;
+    let color = channels != ColorSpace::Monochrome;
+    let orig = Orig::new(bits_per_pixel);
+    let mut transformations = Vec::new();
+
+    // the number of colors doesn't change with the transformations, so the palette is decided
+    // up front
+    let use_palette = permitted.contains(&Transformation::Palette)
+        && color
+        && count_colors(pixels, MAX_ENCODER_PALETTE_SIZE).is_some();
+
+    // compacting only pays off for channels that leave most of their range unused
+    let compact = ChannelCompact::from_pixels(pixels, channels);
+    let sparse = RgbaChannels::ORDER[..channels as usize].iter().any(|&c| {
+        let range = orig.range(c);
+        (compact.range(c).max + 1) * 2 <= range.max - range.min + 1
+    });
+
+    let use_compact = permitted.contains(&Transformation::ChannelCompact) && !use_palette && sparse;
+    let mut transform: Box<dyn Transform> = if use_compact {
+        write_id(rac, Transformation::ChannelCompact)?;
+        compact.write(rac, &orig, update_table)?;
+        forward(pixels, |pixel| compact.forward(pixel));
+        transformations.push(Transformation::ChannelCompact);
+        Box::new(compact)
+    } else {
+        Box::new(orig)
+    };
+
+    // compacted channels keep the order of their values, so the colors still correlate
+    if permitted.contains(&Transformation::YCoGg) && color && has_color(pixels) {
+        let ycocg = YCoGg::new(transform);
+        write_id(rac, Transformation::YCoGg)?;
+        forward(pixels, |pixel| ycocg.forward(pixel));
+        transformations.push(Transformation::YCoGg);
+        transform = Box::new(ycocg);
+    }
+
+    let bounds = Bounds::from_pixels(pixels, transform, channels);
+    transform = if permitted.contains(&Transformation::Bounds) && bounds.is_tighter(channels) {
+        write_id(rac, Transformation::Bounds)?;
+        bounds.write(rac, channels, update_table)?;
+        transformations.push(Transformation::Bounds);
+        Box::new(bounds)
+    } else {
+        bounds.into_previous()
+    };
+
+    if use_palette {
+        let palette = Palette::from_pixels(pixels, transform, MAX_ENCODER_PALETTE_SIZE)
+            .ok_or_else(|| {
+                Error::InvalidOperation("transformations changed the number of colors".into())
+            })?;
+        write_id(rac, Transformation::Palette)?;
+        palette.write(rac, update_table)?;
+        forward(pixels, |pixel| palette.forward(pixel));
+        transformations.push(Transformation::Palette);
+        transform = Box::new(palette);
+    }
+
+    if header.num_frames > 1 {
+        let duplicates = DuplicateFrame::from_pixels(pixels, transform, header);
+        transform =
+            if permitted.contains(&Transformation::DuplicateFrame) && duplicates.has_duplicates() {
+                write_id(rac, Transformation::DuplicateFrame)?;
+                duplicates.write(rac, update_table)?;
+                transformations.push(Transformation::DuplicateFrame);
+                Box::new(duplicates)
+            } else {
+                duplicates.into_previous()
+            };
+
+        let shape = FrameShape::from_pixels(pixels, transform, header);
+        transform = if permitted.contains(&Transformation::FrameShape) && shape.is_partial(header) {
+            write_id(rac, Transformation::FrameShape)?;
+            shape.write(rac, header, update_table)?;
+            transformations.push(Transformation::FrameShape);
+            Box::new(shape)
+        } else {
+            shape.into_previous()
+        };
+    }
+
+    rac.write_bit(false)?;
+    Ok((transformations, transform))
+}
+
+/// Number of distinct colors in `pixels`, `None` if there are more than `max`
+fn count_colors(pixels: &[Rgba], max: usize) -> Option<usize> {
+    let mut colors = HashSet::new();
+    for pixel in pixels {
+        let [r, g, b, _] = pixel.0;
+        colors.insert([r, g, b]);
+        if colors.len() > max {
+            return None;
+        }
+    }
+
+    Some(colors.len())
+}
+
+/// Whether any pixel of `pixels` isn't a shade of grey
+fn has_color(pixels: &[Rgba]) -> bool {
+    pixels.iter().any(|pixel| {
+        let [r, g, b, _] = pixel.0;
+        r != g || g != b
+    })
+}
+
+fn forward<F: Fn(Rgba) -> Rgba>(pixels: &mut [Rgba], transform: F) {
+    for pixel in pixels.iter_mut() {
+        *pixel = transform(*pixel);
+    }
+}
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Default)]
 pub struct ColorRange {
     pub min: ColorValue,
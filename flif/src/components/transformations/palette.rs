@@ -0,0 +1,194 @@
+use super::Transform;
+use crate::components::transformations::ColorRange;
+use crate::error::*;
+use crate::numbers::chances::{ChanceTable, UpdateTable};
+use crate::numbers::near_zero::{NearZeroCoder, NearZeroWriter};
+use crate::numbers::rac::{RacRead, RacWrite};
+use crate::pixels::{ColorSpace, ColorValue, Rgba, RgbaChannels};
+
+const MAX_PALETTE_SIZE: u32 = 30_000;
+
+#[derive(Debug)]
+pub struct Palette {
+    palette: Vec<[ColorValue; 3]>,
+    previous_transformation: Box<dyn Transform>,
+}
+
+impl Palette {
+    pub fn new<R: RacRead>(
+        rac: &mut R,
+        trans: Box<dyn Transform>,
+        channels: ColorSpace,
+        update_table: &UpdateTable,
+    ) -> Result<Palette> {
+        if channels == ColorSpace::Monochrome {
+            return Err(Error::InvalidOperation(
+                "palette transformation requires a color image".into(),
+            ));
+        }
+
+        let mut context = ChanceTable::new(update_table);
+        let size = rac.read_near_zero(1, MAX_PALETTE_SIZE, &mut context)?;
+        let sorted = rac.read_near_zero(0, 1, &mut context)? == 1;
+
+        let mut contexts = [
+            ChanceTable::new(update_table),
+            ChanceTable::new(update_table),
+            ChanceTable::new(update_table),
+        ];
+        let mut min_y = trans.range(RgbaChannels::Red).min;
+        let max_y = trans.range(RgbaChannels::Red).max;
+        let mut palette = Vec::with_capacity(size as usize);
+        for _ in 0..size {
+            let y = rac.read_near_zero(min_y, max_y, &mut contexts[0])?;
+            let range = trans.crange(RgbaChannels::Green, Rgba([y, 0, 0, 0]));
+            let i = rac.read_near_zero(range.min, range.max, &mut contexts[1])?;
+            let range = trans.crange(RgbaChannels::Blue, Rgba([y, i, 0, 0]));
+            let q = rac.read_near_zero(range.min, range.max, &mut contexts[2])?;
+
+            // a sorted palette never decreases in luma
+            if sorted {
+                min_y = y;
+            }
+            palette.push([y, i, q]);
+        }
+
+        Ok(Palette {
+            palette,
+            previous_transformation: trans,
+        })
+    }
+
+    /// Creates a sorted palette of the colors in `pixels`, `None` if there are more than
+    /// `max_size` of them
+    pub fn from_pixels(
+        pixels: &[Rgba],
+        trans: Box<dyn Transform>,
+        max_size: usize,
+    ) -> Option<Palette> {
+        let mut palette: Vec<[ColorValue; 3]> = Vec::new();
+        for pixel in pixels {
+            let [y, i, q, _] = pixel.0;
+            // runs of the same color are common, so skip the search for those
+            if palette.last() == Some(&[y, i, q]) {
+                continue;
+            }
+
+            if let Err(index) = palette.binary_search(&[y, i, q]) {
+                if palette.len() == max_size {
+                    return None;
+                }
+                palette.insert(index, [y, i, q]);
+            }
+        }
+
+        Some(Palette {
+            palette,
+            previous_transformation: trans,
+        })
+    }
+
+    /// Writes the palette the way `new` reads it, it is always sorted
+    pub fn write<W: RacWrite>(&self, rac: &mut W, update_table: &UpdateTable) -> Result<()> {
+        let trans = &self.previous_transformation;
+        let mut context = ChanceTable::new(update_table);
+        rac.write_near_zero(1, MAX_PALETTE_SIZE, self.palette.len() as u32, &mut context)?;
+        rac.write_near_zero(0, 1, 1, &mut context)?;
+
+        let mut contexts = [
+            ChanceTable::new(update_table),
+            ChanceTable::new(update_table),
+            ChanceTable::new(update_table),
+        ];
+        let mut min_y = trans.range(RgbaChannels::Red).min;
+        let max_y = trans.range(RgbaChannels::Red).max;
+        for &[y, i, q] in &self.palette {
+            rac.write_near_zero(min_y, max_y, y, &mut contexts[0])?;
+            let range = trans.crange(RgbaChannels::Green, Rgba([y, 0, 0, 0]));
+            rac.write_near_zero(range.min, range.max, i, &mut contexts[1])?;
+            let range = trans.crange(RgbaChannels::Blue, Rgba([y, i, 0, 0]));
+            rac.write_near_zero(range.min, range.max, q, &mut contexts[2])?;
+            min_y = y;
+        }
+
+        Ok(())
+    }
+
+    pub fn forward(&self, pixel: Rgba) -> Rgba {
+        let [y, i, q, a] = pixel.0;
+        // every color of the image is in the palette, so the search can't fail
+        let index = self.palette.binary_search(&[y, i, q]).unwrap_or(0);
+        Rgba([0, index as ColorValue, 0, a])
+    }
+}
+
+impl Transform for Palette {
+    fn undo(&self, pixel: Rgba) -> Rgba {
+        // a corrupt file can hold indices outside of the palette, those are clamped into it
+        let index = (pixel.0[1].max(0) as usize).min(self.palette.len() - 1);
+        let [y, i, q] = self.palette[index];
+        self.previous_transformation
+            .undo(Rgba([y, i, q, pixel.0[3]]))
+    }
+
+    fn range(&self, channel: RgbaChannels) -> ColorRange {
+        match channel {
+            RgbaChannels::Green => ColorRange {
+                min: 0,
+                max: self.palette.len() as ColorValue - 1,
+            },
+            RgbaChannels::Alpha => self.previous_transformation.range(channel),
+            _ => ColorRange { min: 0, max: 0 },
+        }
+    }
+
+    fn crange(&self, channel: RgbaChannels, _values: Rgba) -> ColorRange {
+        self.range(channel)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Palette, MAX_PALETTE_SIZE};
+    use crate::components::transformations::{Orig, Transform};
+    use crate::numbers::chances::{ChanceTable, UpdateTable};
+    use crate::numbers::near_zero::NearZeroWriter;
+    use crate::numbers::rac::{Rac, RacWrite};
+    use crate::pixels::{ColorSpace, Rgba};
+
+    #[test]
+    fn test_unsorted_palette() {
+        // the luma of the entries goes down, which a sorted palette can't hold
+        let entries = [[200, 1, 2], [10, 20, 30], [100, 50, 0]];
+        let update_table = UpdateTable::new(19, 2);
+        let mut buf = Vec::new();
+        {
+            let mut rac = Rac::from_writer(&mut buf);
+            let mut context = ChanceTable::new(&update_table);
+            rac.write_near_zero(1, MAX_PALETTE_SIZE, entries.len() as u32, &mut context)
+                .unwrap();
+            rac.write_near_zero(0, 1, 0, &mut context).unwrap();
+
+            let mut contexts = [
+                ChanceTable::new(&update_table),
+                ChanceTable::new(&update_table),
+                ChanceTable::new(&update_table),
+            ];
+            for entry in &entries {
+                for (&value, context) in entry.iter().zip(contexts.iter_mut()) {
+                    rac.write_near_zero(0, 255, value, context).unwrap();
+                }
+            }
+            rac.flush().unwrap();
+        }
+
+        let mut rac = Rac::from_reader(buf.as_slice()).unwrap();
+        let orig = Box::new(Orig::new(&[8, 8, 8]));
+        let palette = Palette::new(&mut rac, orig, ColorSpace::RGB, &update_table).unwrap();
+
+        for (index, &[y, i, q]) in entries.iter().enumerate() {
+            let pixel = Rgba([0, index as i32, 0, 255]);
+            assert_eq!([y, i, q, 255], palette.undo(pixel).0);
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use super::Transform;
+use crate::components::transformations::ColorRange;
+use crate::error::*;
+use crate::numbers::chances::{ChanceTable, UpdateTable};
+use crate::numbers::near_zero::NearZeroCoder;
+use crate::numbers::rac::RacRead;
+use crate::pixels::{ColorSpace, ColorValue, Rgba, RgbaChannels};
+
+const MAX_PALETTE_SIZE: u32 = 30_000;
+
+#[derive(Debug)]
+pub struct PaletteAlpha {
+    palette: Vec<Rgba>,
+    previous_transformation: Box<dyn Transform>,
+}
+
+impl PaletteAlpha {
+    pub fn new<R: RacRead>(
+        rac: &mut R,
+        trans: Box<dyn Transform>,
+        channels: ColorSpace,
+        alpha_zero: bool,
+        update_table: &UpdateTable,
+    ) -> Result<PaletteAlpha> {
+        if channels != ColorSpace::RGBA {
+            return Err(Error::InvalidOperation(
+                "alpha palette transformation requires an image with alpha".into(),
+            ));
+        }
+
+        let mut context = ChanceTable::new(update_table);
+        let size = rac.read_near_zero(1, MAX_PALETTE_SIZE, &mut context)?;
+        let sorted = rac.read_near_zero(0, 1, &mut context)? == 1;
+
+        let mut contexts = [
+            ChanceTable::new(update_table),
+            ChanceTable::new(update_table),
+            ChanceTable::new(update_table),
+            ChanceTable::new(update_table),
+        ];
+        let mut min_a = trans.range(RgbaChannels::Alpha).min;
+        let max_a = trans.range(RgbaChannels::Alpha).max;
+        let range_y = trans.range(RgbaChannels::Red);
+        let mut palette = Vec::with_capacity(size as usize);
+        for _ in 0..size {
+            let a = rac.read_near_zero(min_a, max_a, &mut contexts[0])?;
+            // a sorted palette never decreases in alpha
+            if sorted {
+                min_a = a;
+            }
+
+            // the color of invisible entries is irrelevant, so it isn't stored
+            if alpha_zero && a == 0 {
+                palette.push(Rgba([0, 0, 0, 0]));
+                continue;
+            }
+
+            let y = rac.read_near_zero(range_y.min, range_y.max, &mut contexts[1])?;
+            let range = trans.crange(RgbaChannels::Green, Rgba([y, 0, 0, a]));
+            let i = rac.read_near_zero(range.min, range.max, &mut contexts[2])?;
+            let range = trans.crange(RgbaChannels::Blue, Rgba([y, i, 0, a]));
+            let q = rac.read_near_zero(range.min, range.max, &mut contexts[3])?;
+            palette.push(Rgba([y, i, q, a]));
+        }
+
+        Ok(PaletteAlpha {
+            palette,
+            previous_transformation: trans,
+        })
+    }
+}
+
+impl Transform for PaletteAlpha {
+    fn undo(&self, pixel: Rgba) -> Rgba {
+        // a corrupt file can hold indices outside of the palette, those are clamped into it
+        let index = (pixel.0[1].max(0) as usize).min(self.palette.len() - 1);
+        self.previous_transformation.undo(self.palette[index])
+    }
+
+    fn range(&self, channel: RgbaChannels) -> ColorRange {
+        match channel {
+            RgbaChannels::Green => ColorRange {
+                min: 0,
+                max: self.palette.len() as ColorValue - 1,
+            },
+            // every pixel is visible as far as the index plane is concerned
+            RgbaChannels::Alpha => ColorRange { min: 1, max: 1 },
+            _ => ColorRange { min: 0, max: 0 },
+        }
+    }
+
+    fn crange(&self, channel: RgbaChannels, _values: Rgba) -> ColorRange {
+        self.range(channel)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PaletteAlpha, MAX_PALETTE_SIZE};
+    use crate::components::transformations::{Orig, Transform};
+    use crate::numbers::chances::{ChanceTable, UpdateTable};
+    use crate::numbers::near_zero::NearZeroWriter;
+    use crate::numbers::rac::{Rac, RacWrite};
+    use crate::pixels::{ColorSpace, Rgba};
+
+    #[test]
+    fn test_sorted_alpha_zero_palette() {
+        // the invisible entry only stores its alpha
+        let entries = [[0, 0, 0, 0], [10, 20, 30, 128], [5, 6, 7, 255]];
+        let update_table = UpdateTable::new(19, 2);
+        let mut buf = Vec::new();
+        {
+            let mut rac = Rac::from_writer(&mut buf);
+            let mut context = ChanceTable::new(&update_table);
+            rac.write_near_zero(1, MAX_PALETTE_SIZE, entries.len() as u32, &mut context)
+                .unwrap();
+            rac.write_near_zero(0, 1, 1, &mut context).unwrap();
+
+            let mut contexts = [
+                ChanceTable::new(&update_table),
+                ChanceTable::new(&update_table),
+                ChanceTable::new(&update_table),
+                ChanceTable::new(&update_table),
+            ];
+            let mut min_a = 0;
+            for &[y, i, q, a] in &entries {
+                rac.write_near_zero(min_a, 255, a, &mut contexts[0])
+                    .unwrap();
+                min_a = a;
+                if a == 0 {
+                    continue;
+                }
+
+                rac.write_near_zero(0, 255, y, &mut contexts[1]).unwrap();
+                rac.write_near_zero(0, 255, i, &mut contexts[2]).unwrap();
+                rac.write_near_zero(0, 255, q, &mut contexts[3]).unwrap();
+            }
+            rac.flush().unwrap();
+        }
+
+        let mut rac = Rac::from_reader(buf.as_slice()).unwrap();
+        let orig = Box::new(Orig::new(&[8, 8, 8, 8]));
+        let palette =
+            PaletteAlpha::new(&mut rac, orig, ColorSpace::RGBA, true, &update_table).unwrap();
+
+        for (index, &entry) in entries.iter().enumerate() {
+            let pixel = Rgba([0, index as i32, 0, 1]);
+            assert_eq!(entry, palette.undo(pixel).0);
+        }
+    }
+}
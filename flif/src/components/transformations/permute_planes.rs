@@ -1,41 +1,164 @@
+use std::cmp;
+
 use super::Transform;
 use crate::components::transformations::ColorRange;
-use crate::pixels::{Rgba, RgbaChannels};
+use crate::error::*;
+use crate::numbers::chances::{ChanceTable, UpdateTable};
+use crate::numbers::near_zero::NearZeroCoder;
+use crate::numbers::rac::RacRead;
+use crate::pixels::{ColorSpace, Rgba, RgbaChannels};
 
 #[derive(Debug)]
 pub struct PermutePlanes {
-    max: i16,
+    /// Original plane that each plane is taken from
+    permutation: [usize; 4],
+    /// Whether the first plane is subtracted from the second and third
+    subtract: bool,
+    previous_transformation: Box<dyn Transform>,
 }
 
 impl PermutePlanes {
-    pub fn new<T: Transform>(transformation: T) -> PermutePlanes {
-        let max_iter = [
-            transformation.range(RgbaChannels::Red).max,
-            transformation.range(RgbaChannels::Green).max,
-            transformation.range(RgbaChannels::Blue).max,
-        ];
+    pub fn new<R: RacRead>(
+        rac: &mut R,
+        trans: Box<dyn Transform>,
+        channels: ColorSpace,
+        update_table: &UpdateTable,
+    ) -> Result<PermutePlanes> {
+        if channels == ColorSpace::Monochrome {
+            return Err(Error::InvalidOperation(
+                "permute planes transformation requires a color image".into(),
+            ));
+        }
+
+        let planes = channels as usize;
+        let mut context = ChanceTable::new(update_table);
+        let subtract = rac.read_near_zero(0, 1, &mut context)? == 1;
+
+        let mut permutation = [0, 1, 2, 3];
+        let mut used = [false; 4];
+        for source in permutation.iter_mut().take(planes) {
+            *source = rac.read_near_zero(0, planes - 1, &mut context)?;
+            used[*source] = true;
+        }
+
+        if !used[..planes].iter().all(|&used| used) {
+            return Err(Error::InvalidOperation(
+                "plane permutation uses a plane more than once, possibly corrupt file".into(),
+            ));
+        }
+
+        Ok(PermutePlanes {
+            permutation,
+            subtract,
+            previous_transformation: trans,
+        })
+    }
 
-        let old_max = max_iter.iter().max().unwrap();
-        let new_max = (((old_max / 4) + 1) * 4) - 1;
-        PermutePlanes { max: new_max }
+    fn source(&self, channel: RgbaChannels) -> RgbaChannels {
+        match channel {
+            RgbaChannels::Lookback => channel,
+            _ => RgbaChannels::ORDER[self.permutation[channel as usize]],
+        }
     }
 }
 
 impl Transform for PermutePlanes {
     fn undo(&self, pixel: Rgba) -> Rgba {
-        pixel
+        let mut original = [0; 4];
+        for (p, &value) in pixel.0.iter().enumerate() {
+            let source = self.permutation[p];
+            original[source] = if self.subtract && (p == 1 || p == 2) {
+                let range = self
+                    .previous_transformation
+                    .range(RgbaChannels::ORDER[source]);
+                cmp::max(range.min, cmp::min(range.max, value + pixel.0[0]))
+            } else {
+                value
+            };
+        }
+
+        self.previous_transformation.undo(Rgba(original))
     }
 
     fn range(&self, channel: RgbaChannels) -> ColorRange {
-        let min = match channel {
-            RgbaChannels::Red => 0,
-            _ => -self.max,
-        };
+        let range = self.previous_transformation.range(self.source(channel));
+        match channel {
+            RgbaChannels::Green | RgbaChannels::Blue if self.subtract => {
+                let first = self.range(RgbaChannels::Red);
+                ColorRange {
+                    min: range.min - first.max,
+                    max: range.max - first.min,
+                }
+            }
+            _ => range,
+        }
+    }
+
+    fn crange(&self, channel: RgbaChannels, _values: Rgba) -> ColorRange {
+        self.range(channel)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PermutePlanes;
+    use crate::components::transformations::{ColorRange, Transform};
+    use crate::pixels::{Rgba, RgbaChannels};
+
+    #[derive(Debug)]
+    struct Identity;
+
+    impl Transform for Identity {
+        fn undo(&self, pixel: Rgba) -> Rgba {
+            pixel
+        }
+
+        fn range(&self, _channel: RgbaChannels) -> ColorRange {
+            ColorRange { min: 0, max: 255 }
+        }
+
+        fn crange(&self, channel: RgbaChannels, _values: Rgba) -> ColorRange {
+            self.range(channel)
+        }
+    }
+
+    fn permute(permutation: [usize; 4], subtract: bool) -> PermutePlanes {
+        PermutePlanes {
+            permutation,
+            subtract,
+            previous_transformation: Box::new(Identity),
+        }
+    }
+
+    #[test]
+    fn test_undo_permutation() {
+        let transform = permute([1, 2, 0, 3], false);
+        assert_eq!([30, 10, 20, 255], transform.undo(Rgba([10, 20, 30, 255])).0);
+    }
 
-        ColorRange { min, max: self.max }
+    #[test]
+    fn test_undo_subtraction() {
+        // green is coded first, red and blue as differences to it
+        let transform = permute([1, 0, 2, 3], true);
+        assert_eq!(
+            [90, 100, 130, 255],
+            transform.undo(Rgba([100, -10, 30, 255])).0
+        );
     }
 
-    fn crange(&self, _channel: RgbaChannels, _values: Rgba) -> ColorRange {
-        unimplemented!()
+    #[test]
+    fn test_subtracted_ranges() {
+        let transform = permute([1, 0, 2, 3], true);
+        assert_eq!(
+            ColorRange { min: 0, max: 255 },
+            transform.range(RgbaChannels::Red)
+        );
+        assert_eq!(
+            ColorRange {
+                min: -255,
+                max: 255
+            },
+            transform.range(RgbaChannels::Blue)
+        );
     }
 }
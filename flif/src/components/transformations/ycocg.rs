@@ -1,6 +1,6 @@
 use super::Transform;
 use crate::components::transformations::ColorRange;
-use crate::pixels::{Rgba, RgbaChannels};
+use crate::pixels::{ColorValue, Rgba, RgbaChannels};
 
 const R: usize = 0;
 const G: usize = 1;
@@ -8,13 +8,13 @@ const B: usize = 2;
 
 #[derive(Debug)]
 pub struct YCoGg {
-    max: i16,
+    max: ColorValue,
     alpha_range: ColorRange,
-    previous_transformation: Box<Transform>,
+    previous_transformation: Box<dyn Transform>,
 }
 
 impl YCoGg {
-    pub fn new(transformation: Box<Transform>) -> YCoGg {
+    pub fn new(transformation: Box<dyn Transform>) -> YCoGg {
         let max_iter = [
             transformation.range(RgbaChannels::Red).max,
             transformation.range(RgbaChannels::Blue).max,
@@ -29,6 +29,15 @@ impl YCoGg {
             previous_transformation: transformation,
         }
     }
+
+    pub fn forward(&self, pixel: Rgba) -> Rgba {
+        let [red, green, blue, alpha] = pixel.0;
+        let y = (((red + blue) >> 1) + green) >> 1;
+        let co = red - blue;
+        let cg = green - ((red + blue) >> 1);
+
+        Rgba([y, co, cg, alpha])
+    }
 }
 
 impl Transform for YCoGg {
@@ -105,7 +114,34 @@ impl Transform for YCoGg {
 
                 ColorRange { min, max }
             }
-            RgbaChannels::Alpha => self.alpha_range,
+            RgbaChannels::Alpha | RgbaChannels::Lookback => self.alpha_range,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::YCoGg;
+    use crate::components::transformations::{Orig, Transform};
+    use crate::pixels::{Rgba, RgbaChannels};
+
+    #[test]
+    fn test_forward_undo() {
+        let transform = YCoGg::new(Box::new(Orig::new(&[8, 8, 8])));
+        for red in (0..256).step_by(3) {
+            for green in (0..256).step_by(5) {
+                for blue in (0..256).step_by(7) {
+                    let pixel = Rgba([red, green, blue, 0]);
+                    let forward = transform.forward(pixel);
+                    assert_eq!(pixel.0, transform.undo(forward).0);
+
+                    for &channel in &[RgbaChannels::Red, RgbaChannels::Green, RgbaChannels::Blue] {
+                        let range = transform.crange(channel, forward);
+                        let value = forward.0[channel as usize];
+                        assert!(range.min <= value && value <= range.max);
+                    }
+                }
+            }
         }
     }
 }
@@ -1,13 +1,15 @@
 use std::io::Read;
 
-use super::{Flif, FlifInfo, Metadata};
-use crate::components::header::{BytesPerChannel, Header, SecondHeader};
-use crate::decoding_image::DecodingImage;
+use super::{Flif, FlifInfo, Frame, Metadata};
+use crate::coding_image::CodingImage;
+use crate::components::header::{Header, SecondHeader};
 use crate::error::*;
+use crate::frame::FrameData;
+use crate::layout::PixelLayout;
 use crate::numbers::chances::UpdateTable;
 use crate::numbers::rac::Rac;
 use crate::pixels::ColorSpace;
-use crate::pixels::{Greyscale, Rgb, Rgba};
+use crate::pixels::{Greyscale, Pixel, Rgb, Rgba, RgbaLookback};
 use crate::Limits;
 
 pub struct Decoder<R: Read> {
@@ -32,67 +34,186 @@ impl<R: Read> Decoder<R> {
         &self.info
     }
 
-    pub fn decode_image(mut self) -> Result<Flif> {
-        if self.info.header.interlaced {
-            return Err(Error::Unimplemented(
-                "Interlaced images are not yet supported.",
-            ));
+    /// Decodes an interlaced image down to `zoomlevel`, or as far as a truncated input goes.
+    /// The pixels in between are filled in from the decoded ones, so the preview has the full
+    /// size of the image.
+    pub fn decode_preview(mut self, zoomlevel: usize) -> Result<Preview> {
+        if !self.info.header.interlaced {
+            Err(Error::InvalidOperation(
+                "only interlaced images can be previewed".into(),
+            ))?;
         }
 
+        let second_header = &self.info.second_header;
+        let update_table = UpdateTable::new(second_header.alpha_divisor, second_header.cutoff);
+
+        let info = &self.info;
+        let rac = &mut self.rac;
+        let limits = &self.limits;
+        let (raw, reached) = match info.header.channels {
+            _ if info.transform.max_lookback().is_some() => {
+                preview_frames::<RgbaLookback, _>(info, rac, limits, &update_table, zoomlevel)?
+            }
+            ColorSpace::Monochrome => {
+                preview_frames::<Greyscale, _>(info, rac, limits, &update_table, zoomlevel)?
+            }
+            ColorSpace::RGB => {
+                preview_frames::<Rgb, _>(info, rac, limits, &update_table, zoomlevel)?
+            }
+            ColorSpace::RGBA => {
+                preview_frames::<Rgba, _>(info, rac, limits, &update_table, zoomlevel)?
+            }
+        };
+
+        Ok(Preview {
+            image: self.into_flif(raw),
+            zoomlevel: reached,
+            complete: reached <= zoomlevel,
+        })
+    }
+
+    pub fn decode_image(mut self) -> Result<Flif> {
+        let second_header = &self.info.second_header;
+        let update_table = UpdateTable::new(second_header.alpha_divisor, second_header.cutoff);
+
+        let info = &self.info;
+        let rac = &mut self.rac;
+        let limits = &self.limits;
+        let raw = match info.header.channels {
+            // a frame lookback adds its own plane no matter the color space
+            _ if info.transform.max_lookback().is_some() => {
+                decode_frames::<RgbaLookback, _>(info, rac, limits, &update_table)?
+            }
+            ColorSpace::Monochrome => {
+                decode_frames::<Greyscale, _>(info, rac, limits, &update_table)?
+            }
+            ColorSpace::RGB => decode_frames::<Rgb, _>(info, rac, limits, &update_table)?,
+            ColorSpace::RGBA => decode_frames::<Rgba, _>(info, rac, limits, &update_table)?,
+        };
+
+        Ok(self.into_flif(raw))
+    }
+
+    /// Decodes a still image without keeping a copy of its raw pixels. Every row is handed
+    /// to `sink` along with its index as soon as it is final, laid out like `Flif::raw`.
+    /// The rows of an interlaced image only become final at the very end.
+    pub fn decode_rows<F: FnMut(u32, &[u8])>(mut self, sink: F) -> Result<FlifInfo> {
         if self.info.header.num_frames != 1 {
-            return Err(Error::Unimplemented(
-                "Animated images are not yet supported.",
-            ));
+            Err(Error::InvalidOperation(
+                "only still images can be decoded row by row".into(),
+            ))?;
         }
 
-        if self.info.header.bytes_per_channel != BytesPerChannel::One {
-            return Err(Error::Unimplemented(
-                "Only images with 8 bits per channel are supported",
-            ));
+        let second_header = &self.info.second_header;
+        let update_table = UpdateTable::new(second_header.alpha_divisor, second_header.cutoff);
+
+        let info = &self.info;
+        let rac = &mut self.rac;
+        let limits = &self.limits;
+        match info.header.channels {
+            ColorSpace::Monochrome => {
+                stream_rows::<Greyscale, _, _>(info, rac, limits, &update_table, sink)?
+            }
+            ColorSpace::RGB => stream_rows::<Rgb, _, _>(info, rac, limits, &update_table, sink)?,
+            ColorSpace::RGBA => stream_rows::<Rgba, _, _>(info, rac, limits, &update_table, sink)?,
         }
 
-        if self.info.second_header.custom_bitchance {
-            return Err(Error::Unimplemented(
-                "Custom bitchances are currently unimplemented in the FLIF standard.",
-            ));
-        }
+        Ok(self.info)
+    }
 
-        let update_table = UpdateTable::new(
-            self.info.second_header.alpha_divisor,
-            self.info.second_header.cutoff,
-        );
-
-        let raw = match self.info.header.channels {
-            ColorSpace::Monochrome => DecodingImage::<Greyscale, _>::new(
-                &self.info,
-                &mut self.rac,
-                &self.limits,
-                &update_table,
-            )?
-            .process()?,
-            ColorSpace::RGB => DecodingImage::<Rgb, _>::new(
-                &self.info,
-                &mut self.rac,
-                &self.limits,
-                &update_table,
-            )?
-            .process()?,
-            ColorSpace::RGBA => DecodingImage::<Rgba, _>::new(
-                &self.info,
-                &mut self.rac,
-                &self.limits,
-                &update_table,
-            )?
-            .process()?,
-        };
+    /// Decodes a still image straight into `buf`, converting its pixels to the format of
+    /// `layout`. Fails before decoding anything if `buf` is too small for the image.
+    pub fn decode_into(self, buf: &mut [u8], layout: PixelLayout) -> Result<FlifInfo> {
+        let header = &self.info.header;
+        let stride = layout.check(header.width, header.height, buf.len())?;
+        let row_len = header.width as usize * layout.format.bytes_per_pixel();
+        let bits_per_pixel = self.info.second_header.bits_per_pixel.clone();
+
+        self.decode_rows(|y, row| {
+            let start = y as usize * stride;
+            layout.convert_row(row, &bits_per_pixel, &mut buf[start..start + row_len]);
+        })
+    }
 
-        Ok(Flif {
+    fn into_flif(self, raw: Vec<FrameData>) -> Flif {
+        let header = self.info.header;
+        let delays = self.info.second_header.frame_delay.as_ref();
+        let frames = raw
+            .into_iter()
+            .enumerate()
+            .map(|(i, raw)| Frame::new(raw, &header, delays.map_or(0, |delays| delays[i])))
+            .collect();
+
+        Flif {
             info: self.info,
-            raw,
-        })
+            frames,
+        }
+    }
+}
+
+/// A lower resolution version of an interlaced image, created by `Decoder::decode_preview`
+pub struct Preview {
+    image: Flif,
+    zoomlevel: usize,
+    complete: bool,
+}
+
+impl Preview {
+    /// The preview scaled up to the full size of the image
+    pub fn image(&self) -> &Flif {
+        &self.image
+    }
+
+    pub fn into_image(self) -> Flif {
+        self.image
+    }
+
+    /// Zoomlevel every plane was decoded to, 0 is the full image and
+    /// `Header::max_zoomlevel` a single pixel
+    pub fn zoomlevel(&self) -> usize {
+        self.zoomlevel
+    }
+
+    /// Whether the requested zoomlevel was reached, `false` if the input ended before it
+    pub fn is_complete(&self) -> bool {
+        self.complete
     }
 }
 
+fn decode_frames<P: Pixel, R: Read>(
+    info: &FlifInfo,
+    rac: &mut Rac<R>,
+    limits: &Limits,
+    update_table: &UpdateTable,
+) -> Result<Vec<FrameData>> {
+    let mut image = CodingImage::<P, _>::new(info, rac, limits, update_table)?;
+    image.process()?;
+    Ok(image.frames())
+}
+
+fn preview_frames<P: Pixel, R: Read>(
+    info: &FlifInfo,
+    rac: &mut Rac<R>,
+    limits: &Limits,
+    update_table: &UpdateTable,
+    zoomlevel: usize,
+) -> Result<(Vec<FrameData>, usize)> {
+    let mut image = CodingImage::<P, _>::new(info, rac, limits, update_table)?;
+    let reached = image.process_preview(zoomlevel)?;
+    Ok((image.frames(), reached))
+}
+
+fn stream_rows<P: Pixel, R: Read, F: FnMut(u32, &[u8])>(
+    info: &FlifInfo,
+    rac: &mut Rac<R>,
+    limits: &Limits,
+    update_table: &UpdateTable,
+    sink: F,
+) -> Result<()> {
+    let mut image = CodingImage::<P, _>::new(info, rac, limits, update_table)?;
+    image.process_rows(sink)
+}
+
 fn identify_internal<R: Read>(mut reader: R, limits: Limits) -> Result<(FlifInfo, Rac<R>)> {
     // read the first header
     let main_header = Header::from_reader(&mut reader, &limits)?;
@@ -0,0 +1,447 @@
+use std::io::Write;
+
+use super::FlifInfo;
+use crate::coding_image::{quantize, CodingImage, PixelCoder};
+use crate::components::header::{BytesPerChannel, Header, SecondHeader};
+use crate::components::metadata::Metadata;
+use crate::components::transformations::{ColorRange, Transformation};
+use crate::error::*;
+use crate::frame::FrameData;
+use crate::maniac::{LearnedTree, ManiacTree, TreeLearner};
+use crate::numbers::chances::{ChanceTable, UpdateTable};
+use crate::numbers::near_zero::NearZeroWriter;
+use crate::numbers::rac::{Rac, RacWrite};
+use crate::numbers::symbol::UniformSymbolWriter;
+use crate::pixels::{
+    ChannelsTrait, ColorSpace, ColorValue, Greyscale, Pixel, Rgb, Rgba, RgbaChannels,
+};
+use crate::Limits;
+
+use num_traits::PrimInt;
+
+/// Losslessly encodes an image with 8 bits per channel
+///
+/// ```rust,no_run
+/// use std::fs::File;
+/// use flif::components::ColorSpace;
+/// use flif::Encoder;
+///
+/// # fn main() -> flif::Result<()> {
+/// let pixels = vec![0u8; 16 * 16 * 3];
+/// let file = File::create("example.flif")?;
+/// Encoder::new(file, 16, 16, ColorSpace::RGB)?.encode(&pixels)?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Encoder<W: Write> {
+    writer: W,
+    header: Header,
+    options: EncoderOptions,
+    metadata: Vec<Metadata>,
+    loops: u8,
+    quality: u8,
+}
+
+/// Settings that trade encoding speed for the size of the output
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EncoderOptions {
+    /// number of passes over the image used to learn the MANIAC trees, 0 codes every plane
+    /// with a single context (default: 2)
+    pub effort: u8,
+    /// lowest chance of a bit in the context model, from 1 to 128 (default: 2)
+    pub cutoff: u8,
+    /// how slowly the context model adapts, from 2 to 128 (default: 19)
+    pub alpha_divisor: u8,
+    /// drop the color of fully transparent pixels (default: false)
+    pub alpha_zero: bool,
+    /// interlaced predictor that guesses the color of dropped pixels with `alpha_zero`, 0 for
+    /// the average, 1 for the median of the gradients and 2 for the median of the neighbours
+    /// (default: 0)
+    pub invisible_predictor: u8,
+    /// max number of nodes of each MANIAC tree, at most the `Limits::maniac_nodes` decoders
+    /// default to (default: 16384 = 2<sup>14</sup>)
+    pub max_tree_size: u32,
+    /// transformations the encoder may apply, it doesn't write `PermutePlanes`, `PaletteAlpha`,
+    /// `ColorBuckets` and `FrameLookback` (default: every other transformation)
+    pub transformations: Vec<Transformation>,
+}
+
+impl Default for EncoderOptions {
+    fn default() -> Self {
+        Self {
+            effort: 2,
+            cutoff: 2,
+            alpha_divisor: 19,
+            alpha_zero: false,
+            invisible_predictor: 0,
+            max_tree_size: 1 << 14,
+            transformations: vec![
+                Transformation::ChannelCompact,
+                Transformation::YCoGg,
+                Transformation::Bounds,
+                Transformation::Palette,
+                Transformation::DuplicateFrame,
+                Transformation::FrameShape,
+            ],
+        }
+    }
+}
+
+impl<W: Write> Encoder<W> {
+    pub fn new(writer: W, width: u32, height: u32, channels: ColorSpace) -> Result<Self> {
+        Self::with_options(writer, width, height, channels, Default::default())
+    }
+
+    pub fn with_options(
+        writer: W,
+        width: u32,
+        height: u32,
+        channels: ColorSpace,
+        options: EncoderOptions,
+    ) -> Result<Self> {
+        if !(1..=128).contains(&options.cutoff) || !(2..=128).contains(&options.alpha_divisor) {
+            Err(Error::InvalidOperation(
+                "the cutoff ranges from 1 to 128 and the alpha divisor from 2 to 128".into(),
+            ))?;
+        }
+
+        if options.invisible_predictor > 2 {
+            Err(Error::InvalidOperation(
+                "the invisible pixel predictor ranges from 0 to 2".into(),
+            ))?;
+        }
+
+        // larger trees make files that decoders with the default limits reject
+        if options.max_tree_size > Limits::default().maniac_nodes {
+            Err(Error::InvalidOperation(format!(
+                "trees of more than {} nodes can't be decoded with the default limits",
+                Limits::default().maniac_nodes
+            )))?;
+        }
+
+        if width == 0 || height == 0 {
+            Err(Error::InvalidOperation(
+                "an image needs at least one pixel".into(),
+            ))?;
+        }
+
+        Ok(Encoder {
+            writer,
+            header: Header {
+                interlaced: false,
+                channels,
+                bytes_per_channel: BytesPerChannel::One,
+                width,
+                height,
+                num_frames: 1,
+            },
+            options,
+            metadata: Vec::new(),
+            loops: 0,
+            quality: 100,
+        })
+    }
+
+    /// Writes the image interlaced, so that a truncated file still decodes to a lower
+    /// resolution preview of the whole image
+    pub fn interlaced(mut self, interlaced: bool) -> Self {
+        self.header.interlaced = interlaced;
+        self
+    }
+
+    /// Adds metadata chunks such as ICC profiles or EXIF data, their content is compressed
+    /// when writing
+    pub fn metadata(mut self, metadata: Vec<Metadata>) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Sets how many times an animation is played, 0 plays it forever
+    pub fn loops(mut self, loops: u8) -> Self {
+        self.loops = loops;
+        self
+    }
+
+    /// Sets the quality from 0 to 100, anything below 100 quantizes the color residuals so
+    /// that the file gets smaller but no longer decodes to the exact input
+    pub fn quality(mut self, quality: u8) -> Self {
+        self.quality = quality;
+        self
+    }
+
+    /// Encodes `data`, laid out like `Flif::raw`, and returns the writer once the whole image
+    /// has been written
+    pub fn encode(self, data: &[u8]) -> Result<W> {
+        self.encode_frames(&[data], &[0])
+    }
+
+    /// Encodes an animation, every frame is laid out like `Flif::raw` and shown for its delay
+    /// in milliseconds. A single frame is written as a still image.
+    pub fn encode_frames(self, frames: &[&[u8]], delays: &[u16]) -> Result<W> {
+        Ok(self.encode_frames_with_psnr(frames, delays)?.0)
+    }
+
+    /// Like `encode`, also returning the PSNR of the decoded image in dB, which is infinite
+    /// for lossless images
+    pub fn encode_with_psnr(self, data: &[u8]) -> Result<(W, f64)> {
+        self.encode_frames_with_psnr(&[data], &[0])
+    }
+
+    /// Like `encode_frames`, also returning the PSNR of the decoded frames in dB, which is
+    /// infinite for lossless images
+    pub fn encode_frames_with_psnr(mut self, frames: &[&[u8]], delays: &[u16]) -> Result<(W, f64)> {
+        if frames.is_empty() || frames.len() != delays.len() {
+            return Err(Error::InvalidOperation(format!(
+                "expected a delay for each of the {} frames but got {}",
+                frames.len(),
+                delays.len()
+            )));
+        }
+        if self.loops > 100 || delays.iter().any(|&delay| delay > 60_000) {
+            Err(Error::InvalidOperation(
+                "at most 100 loops and delays of 60000 milliseconds can be stored".into(),
+            ))?;
+        }
+        if self.quality > 100 {
+            Err(Error::InvalidOperation(
+                "the quality ranges from 0 to 100".into(),
+            ))?;
+        }
+
+        self.header.num_frames = frames.len() as u32;
+        let header = self.header;
+        let channels = header.channels as usize;
+        let expected = header.width as usize * header.height as usize * channels;
+        if let Some(data) = frames.iter().find(|data| data.len() != expected) {
+            return Err(Error::InvalidOperation(format!(
+                "expected {} bytes of pixel data but got {}",
+                expected,
+                data.len()
+            )));
+        }
+
+        header.to_writer(&mut self.writer)?;
+        Metadata::all_to_writer(&self.metadata, &mut self.writer)?;
+
+        let mut pixels = frames
+            .iter()
+            .flat_map(|data| data.chunks(channels))
+            .map(|raw| {
+                let mut pixel = Rgba([0; 4]);
+                for (value, &raw) in pixel.0.iter_mut().zip(raw) {
+                    *value = ColorValue::from(raw);
+                }
+                pixel
+            })
+            .collect::<Vec<Rgba>>();
+
+        // the color of invisible pixels isn't coded, only alpha is worth keeping
+        let alpha_zero = self.options.alpha_zero
+            && header.channels == ColorSpace::RGBA
+            && pixels.iter().any(|pixel| pixel.0[3] == 0);
+        if alpha_zero {
+            for pixel in pixels.iter_mut().filter(|pixel| pixel.0[3] == 0) {
+                *pixel = Rgba([0; 4]);
+            }
+        }
+
+        let mut rac = Rac::from_writer(self.writer);
+        let bits_per_pixel = vec![8; channels];
+        let animated = header.num_frames > 1;
+        let mut second_header = SecondHeader {
+            bits_per_pixel,
+            alpha_zero,
+            loops: if animated { Some(self.loops) } else { None },
+            frame_delay: if animated {
+                Some(delays.to_vec())
+            } else {
+                None
+            },
+            custom_cutoff: (self.options.cutoff, self.options.alpha_divisor) != (2, 19),
+            cutoff: self.options.cutoff,
+            alpha_divisor: self.options.alpha_divisor,
+            custom_bitchance: false,
+            transformations: Vec::new(),
+            invis_pixel_predictor: Some(self.options.invisible_predictor),
+        };
+        let mut permitted = self.options.transformations.clone();
+        if self.quality < 100 {
+            // quantizing palette indices or compacted values mixes up unrelated colors
+            permitted
+                .retain(|&t| t != Transformation::Palette && t != Transformation::ChannelCompact);
+        }
+        let transform = second_header.write_rac(&header, &mut rac, &mut pixels, &permitted)?;
+
+        let info = FlifInfo {
+            header,
+            metadata: self.metadata,
+            transform,
+            second_header,
+        };
+        let update_table =
+            UpdateTable::new(info.second_header.alpha_divisor, info.second_header.cutoff);
+        let limits = Limits {
+            maniac_nodes: self.options.max_tree_size,
+            ..Default::default()
+        };
+        let step = quantization_step(self.quality);
+        let passes = self.options.effort;
+
+        let (mut rac, decoded) = match header.channels {
+            ColorSpace::Monochrome => encode_frame::<Greyscale, _>(
+                &info,
+                rac,
+                &limits,
+                &update_table,
+                &pixels,
+                step,
+                passes,
+            )?,
+            ColorSpace::RGB => {
+                encode_frame::<Rgb, _>(&info, rac, &limits, &update_table, &pixels, step, passes)?
+            }
+            ColorSpace::RGBA => {
+                encode_frame::<Rgba, _>(&info, rac, &limits, &update_table, &pixels, step, passes)?
+            }
+        };
+
+        // the checksum is optional, so it is left out
+        rac.write_bool(false)?;
+        rac.flush()?;
+        Ok((
+            rac.into_inner(),
+            psnr(frames, &decoded, channels, alpha_zero),
+        ))
+    }
+}
+
+/// Step the residuals are quantized to at `quality`, 1 keeps them exact
+fn quantization_step(quality: u8) -> ColorValue {
+    1 + ColorValue::from(100 - quality) * 64 / 100
+}
+
+/// Peak signal to noise ratio of the decoded frames compared to the input, with `alpha_zero`
+/// only the alpha of invisible pixels counts
+fn psnr(frames: &[&[u8]], decoded: &[FrameData], channels: usize, alpha_zero: bool) -> f64 {
+    let mut squared_error = 0u64;
+    let mut samples = 0u64;
+    for (frame, decoded) in frames.iter().zip(decoded) {
+        if let FrameData::Eight(decoded) = decoded {
+            for (a, b) in frame.chunks(channels).zip(decoded.chunks(channels)) {
+                let first = if alpha_zero && a[3] == 0 { 3 } else { 0 };
+                for (&a, &b) in a[first..].iter().zip(&b[first..]) {
+                    let diff = i64::from(a) - i64::from(b);
+                    squared_error += (diff * diff) as u64;
+                }
+                samples += (a.len() - first) as u64;
+            }
+        }
+    }
+
+    if squared_error == 0 {
+        return f64::INFINITY;
+    }
+    let mse = squared_error as f64 / samples as f64;
+    10.0 * (255.0 * 255.0 / mse).log10()
+}
+
+/// Learns the MANIAC trees of an image and writes its transformed pixels with them. Returns
+/// the frames the way they decode again.
+fn encode_frame<P: Pixel, W: Write>(
+    info: &FlifInfo,
+    rac: Rac<W>,
+    limits: &Limits,
+    update_table: &UpdateTable,
+    transformed: &[Rgba],
+    step: ColorValue,
+    passes: u8,
+) -> Result<(Rac<W>, Vec<FrameData>)> {
+    let pixels = transformed
+        .iter()
+        .map(|rgba| {
+            let mut pixel = P::default();
+            for chan in P::get_chan_order().as_ref() {
+                pixel.set_value(rgba.0[chan.as_channel() as usize], *chan);
+            }
+            pixel
+        })
+        .collect::<Vec<P>>();
+
+    let mut learner = TreeLearner::new(update_table, limits.maniac_nodes as usize, step);
+    for _ in 0..passes {
+        learner.start_pass();
+        let data = pixels.clone();
+        CodingImage::<P, _>::with_data(info, &mut learner, limits, update_table, data)?
+            .process()?;
+    }
+
+    let mut writer = PixelWriter {
+        rac,
+        trees: learner.into_trees(),
+        step,
+    };
+    let mut image =
+        CodingImage::<P, _>::with_data(info, &mut writer, limits, update_table, pixels)?;
+    image.process()?;
+    let decoded = image.frames();
+    Ok((writer.rac, decoded))
+}
+
+/// Writes every symbol of the pixel data to the RAC
+struct PixelWriter<W: Write> {
+    rac: Rac<W>,
+    /// Learned tree of every plane, taken when the tree is written
+    trees: [Option<LearnedTree>; 5],
+    /// Step the residuals are quantized to, 1 when encoding losslessly
+    step: ColorValue,
+}
+
+impl<W: Write> PixelCoder for PixelWriter<W> {
+    fn code_tree(
+        &mut self,
+        channel: RgbaChannels,
+        info: &FlifInfo,
+        update_table: &UpdateTable,
+        limits: &Limits,
+    ) -> Result<ManiacTree> {
+        let tree = self.trees[channel as usize]
+            .take()
+            .unwrap_or_else(LearnedTree::leaf);
+        ManiacTree::write(&mut self.rac, &tree, channel, info, update_table, limits)
+    }
+
+    fn code_pixel(
+        &mut self,
+        channel: RgbaChannels,
+        maniac: &mut ManiacTree,
+        pvec: &[ColorValue],
+        guess: ColorValue,
+        range: ColorRange,
+        value: ColorValue,
+    ) -> Result<ColorValue> {
+        let value = quantize(channel, value, guess, range, self.step);
+        maniac.write_value(&mut self.rac, pvec, guess, range.min, range.max, value)?;
+        Ok(value)
+    }
+
+    fn code_near_zero(
+        &mut self,
+        range: ColorRange,
+        value: ColorValue,
+        context: &mut ChanceTable,
+    ) -> Result<ColorValue> {
+        self.rac
+            .write_near_zero(range.min, range.max, value, context)?;
+        Ok(value)
+    }
+
+    fn code_val<T: PrimInt>(&mut self, min: T, max: T, value: T) -> Result<T> {
+        self.rac.write_val(min, max, value)?;
+        Ok(value)
+    }
+
+    fn code_bool(&mut self, value: bool) -> Result<bool> {
+        self.rac.write_bool(value)?;
+        Ok(value)
+    }
+}
@@ -8,7 +8,9 @@ pub type Result<T> = result::Result<T, Error>;
 #[derive(Debug)]
 pub enum Error {
     Io(io::Error),
-    InvalidHeader { desc: &'static str },
+    InvalidHeader {
+        desc: &'static str,
+    },
     LimitViolation(String),
     UnknownCriticalMetadata([u8; 4]),
     UnknownRequiredMetadata(u8),
@@ -17,9 +19,12 @@ pub enum Error {
     InvalidOperation(String),
     Unimplemented(&'static str),
     UnimplementedTransformation(String),
+    /// The input ended before the image was complete
+    Incomplete,
 }
 
 impl error::Error for Error {
+    #[allow(deprecated)]
     fn description(&self) -> &str {
         match *self {
             Error::InvalidHeader { desc } => desc,
@@ -38,10 +43,11 @@ impl error::Error for Error {
             Error::UnimplementedTransformation(_) => {
                 "a transformation was found which hasn't yet been implemented"
             }
+            Error::Incomplete => "the input ended before the image was complete",
         }
     }
 
-    fn cause(&self) -> Option<&error::Error> {
+    fn cause(&self) -> Option<&dyn error::Error> {
         match *self {
             Error::InvalidHeader { .. } => None,
             Error::Io(ref err) => Some(err),
@@ -53,6 +59,7 @@ impl error::Error for Error {
             Error::InvalidOperation(_) => None,
             Error::Unimplemented(_) => None,
             Error::UnimplementedTransformation(_) => None,
+            Error::Incomplete => None,
         }
     }
 }
@@ -91,6 +98,7 @@ impl fmt::Display for Error {
             Error::UnimplementedTransformation(ref name) => {
                 write!(fmt, "found unimplemented transformation type: {}", name)
             }
+            Error::Incomplete => write!(fmt, "the input ended before the image was complete"),
         }
     }
 }
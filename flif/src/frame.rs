@@ -0,0 +1,249 @@
+use std::iter::FusedIterator;
+use std::mem;
+use std::slice;
+
+use crate::components::header::Header;
+use crate::pixels::{ColorSpace, Grey16, Grey8, Rgb16, Rgb8, Rgba16, Rgba8};
+
+/// Pixel data of a frame, images with more than 8 bits per channel are stored as `u16`
+#[derive(Clone, Debug)]
+pub(crate) enum FrameData {
+    Eight(Box<[u8]>),
+    Sixteen(Box<[u16]>),
+}
+
+/// A single decoded frame of a FLIF image
+#[derive(Clone, Debug)]
+pub struct Frame {
+    data: FrameData,
+    channels: ColorSpace,
+    width: u32,
+    delay: u16,
+}
+
+impl Frame {
+    pub(crate) fn new(data: FrameData, header: &Header, delay: u16) -> Self {
+        Frame {
+            data,
+            channels: header.channels,
+            width: header.width,
+            delay,
+        }
+    }
+
+    /// Raw pixel data of this frame, laid out like `Flif::raw`. For images with more than 8
+    /// bits per channel every channel takes two bytes in native byte order, see `raw_u16`.
+    pub fn raw(&self) -> &[u8] {
+        match self.data {
+            FrameData::Eight(ref raw) => raw,
+            // safe because u8 has no alignment requirements and the length covers the same bytes
+            FrameData::Sixteen(ref raw) => unsafe {
+                slice::from_raw_parts(raw.as_ptr() as *const u8, mem::size_of_val(&**raw))
+            },
+        }
+    }
+
+    pub fn into_raw(self) -> Box<[u8]> {
+        match self.data {
+            FrameData::Eight(raw) => raw,
+            FrameData::Sixteen(raw) => raw.iter().flat_map(|v| v.to_ne_bytes()).collect(),
+        }
+    }
+
+    /// Raw pixel data of this frame with one `u16` per channel, only available for images
+    /// with more than 8 bits per channel.
+    pub fn raw_u16(&self) -> Option<&[u16]> {
+        match self.data {
+            FrameData::Eight(_) => None,
+            FrameData::Sixteen(ref raw) => Some(raw),
+        }
+    }
+
+    /// How long this frame is shown in milliseconds, always 0 for still images
+    pub fn delay(&self) -> u16 {
+        self.delay
+    }
+
+    /// Pixels of this frame typed by the color space and depth of the image
+    pub fn pixels(&self) -> Pixels<'_> {
+        // safe because every pixel type is a `repr(transparent)` array of its channels
+        unsafe {
+            match (&self.data, self.channels) {
+                (FrameData::Eight(ref raw), ColorSpace::Monochrome) => Pixels::Grey8(cast(raw)),
+                (FrameData::Eight(ref raw), ColorSpace::RGB) => Pixels::Rgb8(cast(raw)),
+                (FrameData::Eight(ref raw), ColorSpace::RGBA) => Pixels::Rgba8(cast(raw)),
+                (FrameData::Sixteen(ref raw), ColorSpace::Monochrome) => Pixels::Grey16(cast(raw)),
+                (FrameData::Sixteen(ref raw), ColorSpace::RGB) => Pixels::Rgb16(cast(raw)),
+                (FrameData::Sixteen(ref raw), ColorSpace::RGBA) => Pixels::Rgba16(cast(raw)),
+            }
+        }
+    }
+
+    /// The pixel at column `x` of row `y`, `None` outside of the frame
+    pub fn get_pixel(&self, x: u32, y: u32) -> Option<Color> {
+        if x >= self.width {
+            return None;
+        }
+        self.pixels()
+            .get((y as usize) * self.width as usize + x as usize)
+    }
+
+    /// Iterates over the rows of this frame from top to bottom
+    pub fn rows(&self) -> Rows<'_> {
+        Rows {
+            rest: self.pixels(),
+            width: self.width as usize,
+        }
+    }
+}
+
+/// Views a slice of channels as a slice of pixels made up of those channels
+unsafe fn cast<C, P>(raw: &[C]) -> &[P] {
+    let len = mem::size_of_val(raw) / mem::size_of::<P>();
+    slice::from_raw_parts(raw.as_ptr() as *const P, len)
+}
+
+/// Typed pixels of a frame, created by `Frame::pixels`
+#[derive(Copy, Clone, Debug)]
+pub enum Pixels<'a> {
+    Grey8(&'a [Grey8]),
+    Rgb8(&'a [Rgb8]),
+    Rgba8(&'a [Rgba8]),
+    Grey16(&'a [Grey16]),
+    Rgb16(&'a [Rgb16]),
+    Rgba16(&'a [Rgba16]),
+}
+
+impl<'a> Pixels<'a> {
+    pub fn len(&self) -> usize {
+        match *self {
+            Pixels::Grey8(pixels) => pixels.len(),
+            Pixels::Rgb8(pixels) => pixels.len(),
+            Pixels::Rgba8(pixels) => pixels.len(),
+            Pixels::Grey16(pixels) => pixels.len(),
+            Pixels::Rgb16(pixels) => pixels.len(),
+            Pixels::Rgba16(pixels) => pixels.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<Color> {
+        Some(match *self {
+            Pixels::Grey8(pixels) => Color::Grey8(*pixels.get(index)?),
+            Pixels::Rgb8(pixels) => Color::Rgb8(*pixels.get(index)?),
+            Pixels::Rgba8(pixels) => Color::Rgba8(*pixels.get(index)?),
+            Pixels::Grey16(pixels) => Color::Grey16(*pixels.get(index)?),
+            Pixels::Rgb16(pixels) => Color::Rgb16(*pixels.get(index)?),
+            Pixels::Rgba16(pixels) => Color::Rgba16(*pixels.get(index)?),
+        })
+    }
+
+    fn split_at(self, mid: usize) -> (Pixels<'a>, Pixels<'a>) {
+        match self {
+            Pixels::Grey8(pixels) => {
+                let (head, tail) = pixels.split_at(mid);
+                (Pixels::Grey8(head), Pixels::Grey8(tail))
+            }
+            Pixels::Rgb8(pixels) => {
+                let (head, tail) = pixels.split_at(mid);
+                (Pixels::Rgb8(head), Pixels::Rgb8(tail))
+            }
+            Pixels::Rgba8(pixels) => {
+                let (head, tail) = pixels.split_at(mid);
+                (Pixels::Rgba8(head), Pixels::Rgba8(tail))
+            }
+            Pixels::Grey16(pixels) => {
+                let (head, tail) = pixels.split_at(mid);
+                (Pixels::Grey16(head), Pixels::Grey16(tail))
+            }
+            Pixels::Rgb16(pixels) => {
+                let (head, tail) = pixels.split_at(mid);
+                (Pixels::Rgb16(head), Pixels::Rgb16(tail))
+            }
+            Pixels::Rgba16(pixels) => {
+                let (head, tail) = pixels.split_at(mid);
+                (Pixels::Rgba16(head), Pixels::Rgba16(tail))
+            }
+        }
+    }
+}
+
+/// A single pixel of any color space and depth, returned by `Frame::get_pixel`
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Color {
+    Grey8(Grey8),
+    Rgb8(Rgb8),
+    Rgba8(Rgba8),
+    Grey16(Grey16),
+    Rgb16(Rgb16),
+    Rgba16(Rgba16),
+}
+
+/// Iterator over the rows of a frame, created by `Frame::rows`
+#[derive(Clone, Debug)]
+pub struct Rows<'a> {
+    rest: Pixels<'a>,
+    width: usize,
+}
+
+impl<'a> Iterator for Rows<'a> {
+    type Item = Pixels<'a>;
+
+    fn next(&mut self) -> Option<Pixels<'a>> {
+        if self.rest.is_empty() {
+            return None;
+        }
+
+        let (row, rest) = self.rest.split_at(self.width);
+        self.rest = rest;
+        Some(row)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let rows = self.rest.len() / self.width;
+        (rows, Some(rows))
+    }
+}
+
+impl<'a> ExactSizeIterator for Rows<'a> {}
+
+impl<'a> FusedIterator for Rows<'a> {}
+
+/// Iterator over the frames of a FLIF image, created by `Flif::frames`
+#[derive(Clone, Debug)]
+pub struct Frames<'a> {
+    inner: slice::Iter<'a, Frame>,
+}
+
+impl<'a> Frames<'a> {
+    pub(crate) fn new(frames: &'a [Frame]) -> Self {
+        Frames {
+            inner: frames.iter(),
+        }
+    }
+}
+
+impl<'a> Iterator for Frames<'a> {
+    type Item = &'a Frame;
+
+    fn next(&mut self) -> Option<&'a Frame> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a> DoubleEndedIterator for Frames<'a> {
+    fn next_back(&mut self) -> Option<&'a Frame> {
+        self.inner.next_back()
+    }
+}
+
+impl<'a> ExactSizeIterator for Frames<'a> {}
+
+impl<'a> FusedIterator for Frames<'a> {}
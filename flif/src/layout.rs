@@ -0,0 +1,180 @@
+use crate::error::{Error, Result};
+
+/// Format of the pixels `Decoder::decode_into` writes, every format has 8 bits per channel
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum PixelFormat {
+    Rgba8,
+    Bgra8,
+    Rgb8,
+    Grey8,
+    /// RGBA with the color channels multiplied by alpha
+    PremultipliedRgba8,
+}
+
+impl PixelFormat {
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            PixelFormat::Rgba8 | PixelFormat::Bgra8 | PixelFormat::PremultipliedRgba8 => 4,
+            PixelFormat::Rgb8 => 3,
+            PixelFormat::Grey8 => 1,
+        }
+    }
+}
+
+/// Layout of a buffer the pixels of an image are written into
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct PixelLayout {
+    pub format: PixelFormat,
+    /// bytes from the start of one row to the start of the next, `None` for rows without padding
+    pub stride: Option<usize>,
+}
+
+impl PixelLayout {
+    pub fn new(format: PixelFormat) -> Self {
+        PixelLayout {
+            format,
+            stride: None,
+        }
+    }
+
+    pub fn stride(mut self, stride: usize) -> Self {
+        self.stride = Some(stride);
+        self
+    }
+
+    /// Checks that `len` bytes hold an image of `width` by `height` pixels, returning the
+    /// stride of its rows
+    pub(crate) fn check(&self, width: u32, height: u32, len: usize) -> Result<usize> {
+        let too_large = || Error::InvalidOperation("image is too large for a buffer".into());
+        let row_len = (width as usize)
+            .checked_mul(self.format.bytes_per_pixel())
+            .ok_or_else(too_large)?;
+        let stride = self.stride.unwrap_or(row_len);
+        if stride < row_len {
+            Err(Error::InvalidOperation(format!(
+                "stride of {} bytes is shorter than a row of {} bytes",
+                stride, row_len
+            )))?;
+        }
+
+        let needed = stride
+            .checked_mul(height as usize - 1)
+            .and_then(|rows| rows.checked_add(row_len))
+            .ok_or_else(too_large)?;
+        if len < needed {
+            Err(Error::InvalidOperation(format!(
+                "buffer of {} bytes can't hold the {} bytes of the image",
+                len, needed
+            )))?;
+        }
+
+        Ok(stride)
+    }
+
+    /// Converts a row laid out like `Frame::raw` into `out`, the row has a channel per entry of
+    /// `bits_per_pixel`
+    pub(crate) fn convert_row(&self, row: &[u8], bits_per_pixel: &[u8], out: &mut [u8]) {
+        let channels = bits_per_pixel.len();
+        let pixels = out.len() / self.format.bytes_per_pixel();
+        // channels with more than 8 bits take two bytes
+        let depth = row.len() / (pixels * channels);
+        let value = |i: usize| {
+            let value = if depth == 2 {
+                u32::from(u16::from_ne_bytes([row[i * 2], row[i * 2 + 1]]))
+            } else {
+                u32::from(row[i])
+            };
+            // scale the range of the channel to the 8 bits of the output
+            let max = (1 << bits_per_pixel[i % channels]) - 1;
+            ((value * 255 + max / 2) / max) as u8
+        };
+
+        for (x, out) in out
+            .chunks_exact_mut(self.format.bytes_per_pixel())
+            .enumerate()
+        {
+            let base = x * channels;
+            let [r, g, b, a] = match channels {
+                1 => [value(base), value(base), value(base), 0xFF],
+                3 => [value(base), value(base + 1), value(base + 2), 0xFF],
+                _ => [
+                    value(base),
+                    value(base + 1),
+                    value(base + 2),
+                    value(base + 3),
+                ],
+            };
+
+            match self.format {
+                PixelFormat::Rgba8 => out.copy_from_slice(&[r, g, b, a]),
+                PixelFormat::Bgra8 => out.copy_from_slice(&[b, g, r, a]),
+                PixelFormat::Rgb8 => out.copy_from_slice(&[r, g, b]),
+                PixelFormat::Grey8 if channels == 1 => out[0] = r,
+                PixelFormat::Grey8 => out[0] = luma(r, g, b),
+                PixelFormat::PremultipliedRgba8 => out.copy_from_slice(&[
+                    premultiply(r, a),
+                    premultiply(g, a),
+                    premultiply(b, a),
+                    a,
+                ]),
+            }
+        }
+    }
+}
+
+/// Rec. 601 luma with 8 bit weights
+fn luma(r: u8, g: u8, b: u8) -> u8 {
+    ((77 * u32::from(r) + 150 * u32::from(g) + 29 * u32::from(b) + 128) >> 8) as u8
+}
+
+fn premultiply(value: u8, alpha: u8) -> u8 {
+    ((u32::from(value) * u32::from(alpha) + 127) / 255) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert_row() {
+        let row = [255, 0, 0, 128, 0, 0, 255, 255];
+        let mut out = [0; 8];
+
+        let bits = [8; 4];
+        PixelLayout::new(PixelFormat::Bgra8).convert_row(&row, &bits, &mut out);
+        assert_eq!([0, 0, 255, 128, 255, 0, 0, 255], out);
+
+        PixelLayout::new(PixelFormat::PremultipliedRgba8).convert_row(&row, &bits, &mut out);
+        assert_eq!([128, 0, 0, 128, 0, 0, 255, 255], out);
+
+        let mut grey = [0; 2];
+        PixelLayout::new(PixelFormat::Grey8).convert_row(&row, &bits, &mut grey);
+        assert_eq!([77, 29], grey);
+
+        let row: Vec<u8> = [65535u16, 32768, 0]
+            .iter()
+            .flat_map(|value| value.to_ne_bytes().to_vec())
+            .collect();
+        let mut out = [0; 3];
+        PixelLayout::new(PixelFormat::Rgb8).convert_row(&row, &[16; 3], &mut out);
+        assert_eq!([255, 128, 0], out);
+
+        // channels of few bits are stretched over the whole range
+        PixelLayout::new(PixelFormat::Rgb8).convert_row(&[31, 16, 1], &[5, 5, 2], &mut out);
+        assert_eq!([255, 132, 85], out);
+    }
+
+    #[test]
+    fn test_check() {
+        let layout = PixelLayout::new(PixelFormat::Rgb8);
+        assert_eq!(12, layout.check(4, 2, 24).unwrap());
+        assert!(layout.check(4, 2, 23).is_err());
+        assert_eq!(16, layout.stride(16).check(4, 2, 28).unwrap());
+        assert!(layout.stride(8).check(4, 2, 100).is_err());
+        assert!(layout.stride(usize::MAX).check(4, 2, 100).is_err());
+        assert!(layout
+            .stride(usize::MAX / 2)
+            .check(4, 3, usize::MAX)
+            .is_err());
+    }
+}
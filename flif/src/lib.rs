@@ -21,22 +21,30 @@ use std::io::Read;
 use components::header::{Header, SecondHeader};
 use components::metadata::Metadata;
 use components::transformations::Transform;
-use decoding_image::DecodingImage;
 
-pub use decoder::Decoder;
+pub use decoder::{Decoder, Preview};
+pub use encoder::{Encoder, EncoderOptions};
 pub use error::{Error, Result};
+pub use frame::{Color, Frame, Frames, Pixels, Rows};
+pub use layout::{PixelFormat, PixelLayout};
+pub use pixels::{Grey16, Grey8, Rgb16, Rgb8, Rgba16, Rgba8};
+pub use push_decoder::{Progress, PushDecoder};
 
+mod coding_image;
 pub mod components;
 mod decoder;
-mod decoding_image;
+mod encoder;
 mod error;
+mod frame;
+mod layout;
 mod maniac;
-mod numbers;
+pub mod numbers;
 mod pixels;
+mod push_decoder;
 
 pub struct Flif {
     info: FlifInfo,
-    raw: Box<[u8]>,
+    frames: Vec<Frame>,
 }
 
 impl Flif {
@@ -52,12 +60,49 @@ impl Flif {
         &self.info
     }
 
-    pub fn raw(&self) -> &Box<[u8]> {
-        &self.raw
+    /// Raw pixel data of the first frame
+    pub fn raw(&self) -> &[u8] {
+        self.frames[0].raw()
     }
 
+    /// Raw pixel data of the first frame with one `u16` per channel, only available for
+    /// images with more than 8 bits per channel.
+    pub fn raw_u16(&self) -> Option<&[u16]> {
+        self.frames[0].raw_u16()
+    }
+
+    /// Raw pixel data of the first frame
     pub fn into_raw(self) -> Box<[u8]> {
-        self.raw
+        self.frames
+            .into_iter()
+            .next()
+            .map(Frame::into_raw)
+            .unwrap_or_default()
+    }
+
+    /// Pixels of the first frame typed by the color space and depth of the image
+    pub fn pixels(&self) -> Pixels<'_> {
+        self.frames[0].pixels()
+    }
+
+    /// The pixel of the first frame at column `x` of row `y`, `None` outside of the image
+    pub fn get_pixel(&self, x: u32, y: u32) -> Option<Color> {
+        self.frames[0].get_pixel(x, y)
+    }
+
+    /// Iterates over the rows of the first frame from top to bottom
+    pub fn rows(&self) -> Rows<'_> {
+        self.frames[0].rows()
+    }
+
+    /// Iterates over every frame of the image, a still image has exactly one frame
+    pub fn frames(&self) -> Frames<'_> {
+        Frames::new(&self.frames)
+    }
+
+    /// Number of times an animation should be played, 0 means forever. `None` for still images.
+    pub fn loops(&self) -> Option<u8> {
+        self.info.second_header.loops
     }
 }
 
@@ -90,5 +135,5 @@ pub struct FlifInfo {
     pub header: Header,
     pub metadata: Vec<Metadata>,
     pub second_header: SecondHeader,
-    transform: Box<Transform>,
+    transform: Box<dyn Transform>,
 }
@@ -0,0 +1,546 @@
+use std::cmp;
+
+use crate::coding_image::{quantize, PixelCoder};
+use crate::components::transformations::ColorRange;
+use crate::error::*;
+use crate::maniac::ManiacTree;
+use crate::numbers::chances::{ChanceTable, ChanceTableEntry, UpdateTable};
+use crate::numbers::near_zero::NearZeroWriter;
+use crate::numbers::rac::RacWrite;
+use crate::pixels::{ColorValue, RgbaChannels};
+use crate::FlifInfo;
+use crate::Limits;
+
+use num_traits::PrimInt;
+
+/// Bits a split has to save on the pixels seen so far before it is made
+const SPLIT_THRESHOLD: f64 = 40.0;
+
+/// A node codes `pixels / COUNTER_DIVISOR` pixels with its own context before its split becomes
+/// active, so the children start out with chances that are adapted to the plane
+const COUNTER_DIVISOR: u32 = 16;
+
+/// Shape of a MANIAC tree learned by the encoder
+#[derive(Clone, Debug)]
+pub(crate) struct LearnedTree {
+    nodes: Vec<LearnedNode>,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum LearnedNode {
+    Leaf,
+    Split {
+        property: isize,
+        value: ColorValue,
+        counter: u32,
+        left: usize,
+        right: usize,
+    },
+}
+
+impl LearnedTree {
+    /// A tree that codes the whole plane with a single context
+    pub fn leaf() -> LearnedTree {
+        LearnedTree {
+            nodes: vec![LearnedNode::Leaf],
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Property, counter and test value of every node in pre-order, `None` for leaves
+    pub fn pre_order(&self) -> impl Iterator<Item = Option<(isize, u32, ColorValue)>> {
+        let mut splits = Vec::with_capacity(self.nodes.len());
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            match self.nodes[index] {
+                LearnedNode::Leaf => splits.push(None),
+                LearnedNode::Split {
+                    property,
+                    value,
+                    counter,
+                    left,
+                    right,
+                } => {
+                    splits.push(Some((property, counter, value)));
+                    stack.push(right);
+                    stack.push(left);
+                }
+            }
+        }
+
+        splits.into_iter()
+    }
+}
+
+/// Learns a MANIAC tree for every plane from the pixels it is handed. Every pass over the image
+/// keeps the splits made so far and keeps splitting the leaves.
+pub(crate) struct TreeLearner<'a> {
+    update_table: &'a UpdateTable,
+    /// Cost in bits of coding a bit with each 12 bit chance
+    costs: Vec<f64>,
+    planes: [Option<PlaneLearner>; 5],
+    /// Planes whose tree has been coded during the current pass, only their pixels are learned
+    active: [bool; 5],
+    max_nodes: usize,
+    /// Step the residuals are quantized to, 1 when encoding losslessly
+    step: ColorValue,
+}
+
+impl<'a> TreeLearner<'a> {
+    pub fn new(
+        update_table: &'a UpdateTable,
+        max_nodes: usize,
+        step: ColorValue,
+    ) -> TreeLearner<'a> {
+        let costs = (0..=4096)
+            .map(|chance| match chance {
+                0 => f64::INFINITY,
+                chance => -(f64::from(chance) / 4096.0).log2(),
+            })
+            .collect();
+
+        TreeLearner {
+            update_table,
+            costs,
+            planes: Default::default(),
+            active: [false; 5],
+            max_nodes,
+            step,
+        }
+    }
+
+    /// Prepares the learner for another pass over the image
+    pub fn start_pass(&mut self) {
+        self.active = [false; 5];
+    }
+
+    /// The trees learned for each plane, `None` for planes no pixels were learned for
+    pub fn into_trees(self) -> [Option<LearnedTree>; 5] {
+        let [a, b, c, d, e] = self.planes;
+        [a, b, c, d, e].map(|plane| plane.map(PlaneLearner::into_tree))
+    }
+}
+
+impl PixelCoder for TreeLearner<'_> {
+    fn code_tree(
+        &mut self,
+        channel: RgbaChannels,
+        info: &FlifInfo,
+        update_table: &UpdateTable,
+        _limits: &Limits,
+    ) -> Result<ManiacTree> {
+        let update = self.update_table;
+        let plane = self.planes[channel as usize].get_or_insert_with(|| {
+            PlaneLearner::new(ManiacTree::build_prange_vec(channel, info), update)
+        });
+        plane.reset();
+        self.active[channel as usize] = true;
+
+        Ok(ManiacTree::leaf(update_table))
+    }
+
+    fn code_pixel(
+        &mut self,
+        channel: RgbaChannels,
+        _maniac: &mut ManiacTree,
+        pvec: &[ColorValue],
+        guess: ColorValue,
+        range: ColorRange,
+        value: ColorValue,
+    ) -> Result<ColorValue> {
+        let value = quantize(channel, value, guess, range, self.step);
+        if range.min == range.max || !self.active[channel as usize] {
+            return Ok(value);
+        }
+
+        if let Some(ref mut plane) = self.planes[channel as usize] {
+            let residual = ColorRange {
+                min: range.min - guess,
+                max: range.max - guess,
+            };
+            plane.learn(pvec, residual, value - guess, &self.costs, self.max_nodes)?;
+        }
+
+        Ok(value)
+    }
+
+    fn code_near_zero(
+        &mut self,
+        _range: ColorRange,
+        value: ColorValue,
+        _context: &mut ChanceTable,
+    ) -> Result<ColorValue> {
+        Ok(value)
+    }
+
+    fn code_val<T: PrimInt>(&mut self, _min: T, _max: T, value: T) -> Result<T> {
+        Ok(value)
+    }
+
+    fn code_bool(&mut self, value: bool) -> Result<bool> {
+        Ok(value)
+    }
+}
+
+struct PlaneLearner {
+    nodes: Vec<LearningNode>,
+}
+
+impl PlaneLearner {
+    fn new(prange: Vec<ColorRange>, update_table: &UpdateTable) -> PlaneLearner {
+        PlaneLearner {
+            nodes: vec![LearningNode::new(prange, ChanceTable::new(update_table))],
+        }
+    }
+
+    fn reset(&mut self) {
+        for node in self.nodes.iter_mut().filter(|node| node.split.is_none()) {
+            let table = node.table.clone();
+            *node = LearningNode::new(node.prange.clone(), table);
+        }
+    }
+
+    fn learn(
+        &mut self,
+        pvec: &[ColorValue],
+        range: ColorRange,
+        value: ColorValue,
+        costs: &[f64],
+        max_nodes: usize,
+    ) -> Result<()> {
+        let mut index = 0;
+        while let Some(split) = self.nodes[index].split {
+            index = if pvec[split.property] > split.value {
+                split.left
+            } else {
+                split.right
+            };
+        }
+
+        let node = &mut self.nodes[index];
+        node.count += 1;
+        node.cost += estimate(costs, &mut node.table, range, value)?;
+        for (p, virtual_split) in node.virtual_splits.iter_mut().enumerate() {
+            if node.prange[p].min >= node.prange[p].max {
+                continue;
+            }
+
+            node.sums[p] += i64::from(pvec[p]);
+            let split_value = split_value(node.sums[p], node.count, node.prange[p]);
+            let table = if pvec[p] > split_value {
+                &mut virtual_split.left
+            } else {
+                &mut virtual_split.right
+            };
+            virtual_split.cost += estimate(costs, table, range, value)?;
+        }
+
+        if self.nodes.len() + 2 <= max_nodes {
+            self.try_split(index);
+        }
+
+        Ok(())
+    }
+
+    /// Splits the leaf at `index` on the property whose virtual split saves the most bits
+    fn try_split(&mut self, index: usize) {
+        let node = &self.nodes[index];
+        let best = node
+            .virtual_splits
+            .iter()
+            .enumerate()
+            .filter(|&(p, _)| node.prange[p].min < node.prange[p].max)
+            .min_by(|(_, a), (_, b)| a.cost.total_cmp(&b.cost));
+        let (property, virtual_split) = match best {
+            Some((p, virtual_split)) if node.cost - virtual_split.cost > SPLIT_THRESHOLD => {
+                (p, virtual_split)
+            }
+            _ => return,
+        };
+
+        let value = split_value(node.sums[property], node.count, node.prange[property]);
+        let mut left_prange = node.prange.clone();
+        left_prange[property].min = value + 1;
+        let mut right_prange = node.prange.clone();
+        right_prange[property].max = value;
+
+        let left = LearningNode::new(left_prange, virtual_split.left.clone());
+        let right = LearningNode::new(right_prange, virtual_split.right.clone());
+        let counter = (node.count / COUNTER_DIVISOR).clamp(1, 512);
+
+        let left_index = self.nodes.len();
+        self.nodes.push(left);
+        self.nodes.push(right);
+
+        let node = &mut self.nodes[index];
+        node.split = Some(Split {
+            property,
+            value,
+            counter,
+            left: left_index,
+            right: left_index + 1,
+        });
+        // only leaves learn, so the statistics are no longer needed
+        node.virtual_splits = Vec::new();
+        node.sums = Vec::new();
+    }
+
+    fn into_tree(self) -> LearnedTree {
+        let nodes = self
+            .nodes
+            .iter()
+            .map(|node| match node.split {
+                None => LearnedNode::Leaf,
+                Some(split) => LearnedNode::Split {
+                    property: split.property as isize,
+                    value: split.value,
+                    counter: split.counter,
+                    left: split.left,
+                    right: split.right,
+                },
+            })
+            .collect();
+
+        LearnedTree { nodes }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Split {
+    property: usize,
+    value: ColorValue,
+    counter: u32,
+    left: usize,
+    right: usize,
+}
+
+struct LearningNode {
+    split: Option<Split>,
+    /// Range of every property for the pixels reaching this node
+    prange: Vec<ColorRange>,
+    table: ChanceTable,
+    /// Number of pixels that reached this node during the current pass
+    count: u32,
+    /// Estimated bits spent on those pixels with `table`
+    cost: f64,
+    /// Sum of every property over those pixels, the virtual splits are made at the average
+    sums: Vec<i64>,
+    virtual_splits: Vec<VirtualSplit>,
+}
+
+impl LearningNode {
+    fn new(prange: Vec<ColorRange>, table: ChanceTable) -> LearningNode {
+        let properties = prange.len();
+        let virtual_split = VirtualSplit {
+            left: table.clone(),
+            right: table.clone(),
+            cost: 0.0,
+        };
+
+        LearningNode {
+            split: None,
+            prange,
+            table,
+            count: 0,
+            cost: 0.0,
+            sums: vec![0; properties],
+            virtual_splits: vec![virtual_split; properties],
+        }
+    }
+}
+
+/// Contexts of the children a node would get by splitting on a property
+#[derive(Clone)]
+struct VirtualSplit {
+    left: ChanceTable,
+    right: ChanceTable,
+    /// Estimated bits spent on the pixels seen so far if the node had been split
+    cost: f64,
+}
+
+/// Average of a property, clamped so that both sides of a split on it are possible
+fn split_value(sum: i64, count: u32, range: ColorRange) -> ColorValue {
+    let average = sum / i64::from(cmp::max(count, 1));
+    let average = cmp::max(
+        i64::from(range.min),
+        cmp::min(i64::from(range.max) - 1, average),
+    );
+    average as ColorValue
+}
+
+/// Estimated bits needed to code `value` with `table`, updating `table` like coding would
+fn estimate(
+    costs: &[f64],
+    table: &mut ChanceTable,
+    range: ColorRange,
+    value: ColorValue,
+) -> Result<f64> {
+    let mut estimator = CostEstimator { costs, bits: 0.0 };
+    estimator.write_near_zero(range.min, range.max, value, table)?;
+    Ok(estimator.bits)
+}
+
+/// Range coder that only counts the bits it would write
+struct CostEstimator<'c> {
+    costs: &'c [f64],
+    bits: f64,
+}
+
+impl<'c> RacWrite for CostEstimator<'c> {
+    fn write_bit(&mut self, _bit: bool) -> Result<()> {
+        self.bits += 1.0;
+        Ok(())
+    }
+
+    fn write_chance(&mut self, chance: u32, bit: bool) -> Result<()> {
+        let chance = if bit { chance } else { 4096 - chance };
+        self.bits += self.costs[chance as usize];
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn write(
+        &mut self,
+        context: &mut ChanceTable,
+        entry: ChanceTableEntry,
+        bit: bool,
+    ) -> Result<()> {
+        self.write_chance(u32::from(context.get_chance(entry)), bit)?;
+        context.update_entry(bit, entry);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PlaneLearner, TreeLearner};
+    use crate::components::header::{BytesPerChannel, Header, SecondHeader};
+    use crate::components::transformations::{ColorRange, Orig};
+    use crate::maniac::{ManiacNode, ManiacTree};
+    use crate::numbers::chances::UpdateTable;
+    use crate::numbers::rac::{Rac, RacWrite};
+    use crate::pixels::{ColorSpace, RgbaChannels};
+    use crate::{FlifInfo, Limits};
+
+    fn grey_info() -> FlifInfo {
+        FlifInfo {
+            header: Header {
+                interlaced: false,
+                channels: ColorSpace::Monochrome,
+                bytes_per_channel: BytesPerChannel::One,
+                width: 64,
+                height: 64,
+                num_frames: 1,
+            },
+            metadata: Vec::new(),
+            second_header: SecondHeader {
+                bits_per_pixel: vec![8],
+                alpha_zero: false,
+                loops: None,
+                frame_delay: None,
+                custom_cutoff: false,
+                cutoff: 2,
+                alpha_divisor: 19,
+                custom_bitchance: false,
+                transformations: Vec::new(),
+                invis_pixel_predictor: None,
+            },
+            transform: Box::new(Orig::new(&[8])),
+        }
+    }
+
+    /// Shape of every node, leaving out the contexts
+    fn shape(tree: &ManiacTree) -> Vec<(u8, isize, i32, u32, usize, usize)> {
+        tree.nodes
+            .iter()
+            .map(|node| match *node {
+                ManiacNode::Property {
+                    id,
+                    value,
+                    counter,
+                    left,
+                    right,
+                    ..
+                } => (0, id, value, counter, left, right),
+                ManiacNode::InactiveProperty {
+                    id,
+                    value,
+                    counter,
+                    left,
+                    right,
+                } => (1, id, value, counter, left, right),
+                ManiacNode::Inner {
+                    id,
+                    value,
+                    left,
+                    right,
+                } => (2, id, value, 0, left, right),
+                ManiacNode::Leaf(_) => (3, 0, 0, 0, 0, 0),
+                ManiacNode::InactiveLeaf => (4, 0, 0, 0, 0, 0),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_learned_tree_reads_back() {
+        let info = grey_info();
+        let update_table = UpdateTable::new(19, 2);
+        let limits = Limits::default();
+        let prange = ManiacTree::build_prange_vec(RgbaChannels::Red, &info);
+        let costs = TreeLearner::new(&update_table, 0, 1).costs;
+
+        // residuals that depend on the first two properties, so splitting on them pays off
+        let mut plane = PlaneLearner::new(prange.clone(), &update_table);
+        let mut seed = 12345u32;
+        for _ in 0..20_000 {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            let mut pvec = [0; 7];
+            for (p, value) in pvec.iter_mut().enumerate() {
+                let range = prange[p];
+                let span = (range.max - range.min + 1) as u32;
+                *value = range.min + ((seed >> (p * 3)) % span) as i32;
+            }
+            let value = match (pvec[0] > 128, pvec[1] > 1) {
+                (true, true) => 40,
+                (true, false) => -20,
+                (false, _) => (seed >> 24) as i32 % 3,
+            };
+            let range = ColorRange {
+                min: -255,
+                max: 255,
+            };
+            plane.learn(&pvec, range, value, &costs, 1 << 10).unwrap();
+        }
+        let learned = plane.into_tree();
+        assert!(learned.size() > 1, "no split was learned");
+
+        let mut buf = Vec::new();
+        let written = {
+            let mut rac = Rac::from_writer(&mut buf);
+            let tree = ManiacTree::write(
+                &mut rac,
+                &learned,
+                RgbaChannels::Red,
+                &info,
+                &update_table,
+                &limits,
+            )
+            .unwrap();
+            rac.flush().unwrap();
+            tree
+        };
+
+        let mut rac = Rac::from_reader(buf.as_slice()).unwrap();
+        let read =
+            ManiacTree::new(&mut rac, RgbaChannels::Red, &info, &update_table, &limits).unwrap();
+        assert_eq!(learned.size(), read.size());
+        assert_eq!(shape(&written), shape(&read));
+    }
+}
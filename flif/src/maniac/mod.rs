@@ -1,51 +1,145 @@
 #![allow(unused)]
 
-use std::io::Read;
+use std::io::{Read, Write};
 
 use crate::components::transformations::ColorRange;
 use crate::components::transformations::Transform;
 use crate::error::*;
 use crate::numbers::chances::{ChanceTable, UpdateTable};
-use crate::numbers::near_zero::NearZeroCoder;
-use crate::numbers::rac::{Rac, RacRead};
+use crate::numbers::near_zero::{NearZeroCoder, NearZeroWriter};
+use crate::numbers::rac::{Rac, RacRead, RacWrite};
 use crate::pixels::{ColorSpace, ColorValue, RgbaChannels};
-use crate::DecodingImage;
 use crate::FlifInfo;
 use crate::Limits;
 
+mod learning;
 mod pvec;
-pub(crate) use self::pvec::{core_pvec, edge_pvec};
+pub(crate) use self::learning::{LearnedTree, TreeLearner};
+pub(crate) use self::pvec::{core_pvec, edge_pvec, interlaced_pvec};
 
-pub struct ManiacTree<'a> {
-    nodes: Vec<ManiacNode<'a>>,
+pub struct ManiacTree {
+    nodes: Vec<ManiacNode>,
+    /// Nodes as they were before they changed since the last `checkpoint`, latest last
+    journal: Option<Vec<(usize, ManiacNode)>>,
 }
 
-impl<'a> ManiacTree<'a> {
+impl ManiacTree {
     pub fn new<R: Read>(
         rac: &mut Rac<R>,
         channel: RgbaChannels,
         info: &FlifInfo,
-        update_table: &'a UpdateTable,
+        update_table: &UpdateTable,
         limits: &Limits,
-    ) -> Result<ManiacTree<'a>> {
-        let context_a = ChanceTable::new(update_table);
-        let context_b = ChanceTable::new(update_table);
-        let context_c = ChanceTable::new(update_table);
+    ) -> Result<ManiacTree> {
+        let mut context = [
+            ChanceTable::new(update_table),
+            ChanceTable::new(update_table),
+            ChanceTable::new(update_table),
+        ];
 
         let prange = Self::build_prange_vec(channel, info);
-        let nodes = Self::create_nodes(
-            rac,
-            &mut [context_a, context_b, context_c],
-            update_table,
-            prange,
-            limits,
-        )?;
-
-        Ok(ManiacTree { nodes })
+        let nodes = Self::create_nodes(update_table, prange, limits, |prange| {
+            let property = rac.read_near_zero(0, prange.len() as isize, &mut context[0])?;
+            if property == 0 {
+                return Ok(None);
+            }
+
+            let range = prange
+                .get(property as usize - 1)
+                .cloned()
+                .unwrap_or_default();
+            if range.min >= range.max {
+                Err(Error::InvalidOperation("Invalid maniac tree".into()))?
+            }
+
+            let counter = rac.read_near_zero(1, 512, &mut context[1])?;
+            let test_value = rac.read_near_zero(range.min, range.max - 1, &mut context[2])?;
+            Ok(Some((property - 1, counter, test_value)))
+        })?;
+
+        Ok(ManiacTree {
+            nodes,
+            journal: None,
+        })
+    }
+
+    /// Writes `tree` the way `new` reads it and returns the tree the decoder will end up with
+    pub fn write<W: RacWrite>(
+        rac: &mut W,
+        tree: &LearnedTree,
+        channel: RgbaChannels,
+        info: &FlifInfo,
+        update_table: &UpdateTable,
+        limits: &Limits,
+    ) -> Result<ManiacTree> {
+        let mut context = [
+            ChanceTable::new(update_table),
+            ChanceTable::new(update_table),
+            ChanceTable::new(update_table),
+        ];
+
+        // the nodes are created in pre-order, the same order the learned tree is laid out in
+        let mut learned = tree.pre_order();
+        let prange = Self::build_prange_vec(channel, info);
+        let nodes = Self::create_nodes(update_table, prange, limits, |prange| {
+            let split = learned.next().ok_or_else(|| {
+                Error::InvalidOperation("learned maniac tree is incomplete".into())
+            })?;
+            let (property, counter, value) = match split {
+                Some(split) => split,
+                None => {
+                    rac.write_near_zero(0, prange.len() as isize, 0, &mut context[0])?;
+                    return Ok(None);
+                }
+            };
+
+            let range = prange.get(property as usize).cloned().unwrap_or_default();
+            if range.min > value || value >= range.max {
+                Err(Error::InvalidOperation(
+                    "learned maniac tree splits outside of the property range".into(),
+                ))?
+            }
+
+            rac.write_near_zero(0, prange.len() as isize, property + 1, &mut context[0])?;
+            rac.write_near_zero(1, 512, counter, &mut context[1])?;
+            rac.write_near_zero(range.min, range.max - 1, value, &mut context[2])?;
+            Ok(Some((property, counter, value)))
+        })?;
+
+        Ok(ManiacTree {
+            nodes,
+            journal: None,
+        })
+    }
+
+    /// Creates a tree consisting of a single leaf, used for the rough zoomlevels of
+    /// interlaced images which are coded before the real trees.
+    pub fn leaf(update_table: &UpdateTable) -> ManiacTree {
+        ManiacTree {
+            nodes: vec![ManiacNode::Leaf(ChanceTable::new(update_table))],
+            journal: None,
+        }
     }
 
     pub fn size(&self) -> usize {
-        return self.nodes.len();
+        self.nodes.len()
+    }
+
+    /// Starts keeping track of the changes to the contexts, so that they can be rolled back
+    pub fn checkpoint(&mut self) {
+        self.journal = Some(Vec::new());
+    }
+
+    /// Undoes every change since the last `checkpoint`
+    pub fn rollback(&mut self) {
+        for (index, node) in self.journal.take().into_iter().flatten().rev() {
+            self.nodes[index] = node;
+        }
+    }
+
+    /// Keeps the changes since the last `checkpoint`
+    pub fn commit(&mut self) {
+        self.journal = None;
     }
 
     pub fn depth(&self) -> usize {
@@ -88,52 +182,83 @@ impl<'a> ManiacTree<'a> {
             return Ok(min);
         }
 
-        let val = self.apply(rac, pvec, min - guess, max - guess)?;
+        let (min, max) = (min - guess, max - guess);
+        let val = self.apply(pvec, |table| rac.read_near_zero(min, max, table))?;
         Ok(val + guess)
     }
 
-    fn create_nodes<R: Read>(
-        rac: &mut Rac<R>,
-        context: &mut [ChanceTable; 3],
-        update_table: &'a UpdateTable,
+    /// Writes `value` the way `process` reads it back
+    pub fn write_value<W: RacWrite>(
+        &mut self,
+        rac: &mut W,
+        pvec: &[ColorValue],
+        guess: ColorValue,
+        min: ColorValue,
+        max: ColorValue,
+        value: ColorValue,
+    ) -> Result<()> {
+        if min == max {
+            return Ok(());
+        }
+
+        let (min, max, value) = (min - guess, max - guess, value - guess);
+        self.apply(pvec, |table| {
+            rac.write_near_zero(min, max, value, table)?;
+            Ok(value)
+        })?;
+        Ok(())
+    }
+
+    /// Creates the nodes of a tree in pre-order, `code_node` codes the split of each node and
+    /// returns its property, counter and test value, or `None` for leaves.
+    fn create_nodes<F>(
+        update_table: &UpdateTable,
         prange: Vec<ColorRange>,
         limits: &Limits,
-    ) -> Result<Vec<ManiacNode<'a>>> {
+        mut code_node: F,
+    ) -> Result<Vec<ManiacNode>>
+    where
+        F: FnMut(&[ColorRange]) -> Result<Option<(isize, u32, ColorValue)>>,
+    {
         use self::ManiacNode::*;
 
         let mut result_vec = vec![ManiacNode::InactiveLeaf];
         let mut process_stack = vec![(0, prange)];
-        loop {
-            let (index, prange) = match process_stack.pop() {
-                Some(process) => process,
-                _ => break,
-            };
-
+        while let Some((index, prange)) = process_stack.pop() {
             if result_vec.len() > limits.maniac_nodes as usize {
-                Err(Error::LimitViolation(format!(
-                    "number of maniac nodes exceeds limit"
-                )))?;
+                Err(Error::LimitViolation(
+                    "number of maniac nodes exceeds limit".into(),
+                ))?;
             }
 
-            let child_start = result_vec.len();
-            let node = if index == 0 {
-                Self::create_node(child_start, rac, context, update_table, &prange)?
-            } else {
-                Self::create_inner_node(child_start, rac, context, &prange)?
+            let (property, counter, test_value) = match code_node(&prange)? {
+                Some(split) => split,
+                // only the root starts out active, every other node is activated by its parent
+                None if index == 0 => {
+                    result_vec[index] = Leaf(ChanceTable::new(update_table));
+                    continue;
+                }
+                None => continue,
             };
 
-            let (property, test_value) = match node {
-                Property { id, value, .. }
-                | InactiveProperty { id, value, .. }
-                | Inner { id, value, .. } => {
-                    if child_start >= result_vec.len() {
-                        result_vec.resize(child_start + 2, ManiacNode::InactiveLeaf);
-                    }
-                    (id, value)
+            let child_start = result_vec.len();
+            result_vec.resize(child_start + 2, ManiacNode::InactiveLeaf);
+            result_vec[index] = if index == 0 {
+                Property {
+                    id: property,
+                    table: ChanceTable::new(update_table),
+                    value: test_value,
+                    counter,
+                    left: child_start,
+                    right: child_start + 1,
                 }
-                _ => {
-                    result_vec[index] = node;
-                    continue;
+            } else {
+                InactiveProperty {
+                    id: property,
+                    value: test_value,
+                    counter,
+                    left: child_start,
+                    right: child_start + 1,
                 }
             };
 
@@ -145,93 +270,35 @@ impl<'a> ManiacTree<'a> {
 
             process_stack.push((child_start + 1, right_prange));
             process_stack.push((child_start, left_prange));
-            result_vec[index] = node;
         }
 
         Ok(result_vec)
     }
 
-    fn create_node<R: Read>(
-        child_start: usize,
-        rac: &mut Rac<R>,
-        context: &mut [ChanceTable; 3],
-        update_table: &'a UpdateTable,
-        prange: &[ColorRange],
-    ) -> Result<ManiacNode<'a>> {
-        let chance_table = ChanceTable::new(update_table);
-        let mut property = rac.read_near_zero(0, prange.len() as isize, &mut context[0])?;
-
-        if property == 0 {
-            return Ok(ManiacNode::Leaf(chance_table));
-        }
-        property -= 1;
-
-        if prange[property as usize].min >= prange[property as usize].max {
-            Err(Error::InvalidOperation(format!("Invalid maniac tree")))?
-        }
-
-        let counter = rac.read_near_zero(1 as i32, 512 as i32, &mut context[1])?;
-        let test_value = rac.read_near_zero(
-            prange[property as usize].min,
-            prange[property as usize].max - 1,
-            &mut context[2],
-        )?;
-
-        Ok(ManiacNode::Property {
-            id: property,
-            table: chance_table,
-            value: test_value,
-            counter: counter as u32,
-            left: child_start,
-            right: child_start + 1,
-        })
-    }
-
-    fn create_inner_node<R: Read>(
-        child_start: usize,
-        rac: &mut Rac<R>,
-        context: &mut [ChanceTable; 3],
-        prange: &[ColorRange],
-    ) -> Result<ManiacNode<'a>> {
-        let mut property = rac.read_near_zero(0, prange.len() as isize, &mut context[0])?;
-
-        if property == 0 {
-            return Ok(ManiacNode::InactiveLeaf);
-        }
-        property -= 1;
-
-        if prange[property as usize].min >= prange[property as usize].max {
-            Err(Error::InvalidOperation(format!("Invalid maniac tree")))?
-        }
-
-        let counter = rac.read_near_zero(1 as i32, 512 as i32, &mut context[1])?;
-        let test_value = rac.read_near_zero(
-            prange[property as usize].min,
-            prange[property as usize].max - 1,
-            &mut context[2],
-        )?;
-
-        Ok(ManiacNode::InactiveProperty {
-            id: property,
-            value: test_value,
-            counter: counter as u32,
-            left: child_start,
-            right: child_start + 1,
-        })
-    }
-
-    pub fn apply<R: Read>(
-        &mut self,
-        rac: &mut Rac<R>,
-        pvec: &[ColorValue],
-        min: ColorValue,
-        max: ColorValue,
-    ) -> Result<ColorValue> {
+    /// Finds the context `pvec` belongs to and codes a value with it, activating the children
+    /// of property nodes whose counter runs out
+    pub fn apply<F>(&mut self, pvec: &[ColorValue], code: F) -> Result<ColorValue>
+    where
+        F: FnOnce(&mut ChanceTable) -> Result<ColorValue>,
+    {
         use self::ManiacNode::*;
         let mut node_index = 0;
         loop {
             let (lnodes, rnodes) = &mut self.nodes.split_at_mut(node_index + 1);
             let node = &mut lnodes[node_index];
+            if let (Some(journal), Leaf(_) | Property { .. }) = (&mut self.journal, &*node) {
+                journal.push((node_index, node.clone()));
+                if let Property {
+                    counter: 0,
+                    left,
+                    right,
+                    ..
+                } = *node
+                {
+                    journal.push((left, rnodes[left - node_index - 1].clone()));
+                    journal.push((right, rnodes[right - node_index - 1].clone()));
+                }
+            }
             match node {
                 Inner {
                     id,
@@ -246,7 +313,7 @@ impl<'a> ManiacTree<'a> {
                     }
                 }
                 Leaf(table) => {
-                    return rac.read_near_zero(min, max, table);
+                    return code(table);
                 }
                 node => {
                     let (val, new_node) = match node {
@@ -262,9 +329,9 @@ impl<'a> ManiacTree<'a> {
                             let mut right_table = table.clone();
 
                             let val = if pvec[*id as usize] > *value {
-                                rac.read_near_zero(min, max, &mut left_table)?
+                                code(&mut left_table)?
                             } else {
-                                rac.read_near_zero(min, max, &mut right_table)?
+                                code(&mut right_table)?
                             };
 
                             rnodes[*left - node_index - 1].activate(left_table);
@@ -281,7 +348,7 @@ impl<'a> ManiacTree<'a> {
                         }
                         Property { counter, table, .. } => {
                             *counter -= 1;
-                            return rac.read_near_zero(min, max, table);
+                            return code(table);
                         }
                         _ => panic!(
                             "improperly constructed tree, \
@@ -295,7 +362,7 @@ impl<'a> ManiacTree<'a> {
         }
     }
 
-    fn build_prange_vec(channel: RgbaChannels, info: &FlifInfo) -> Vec<ColorRange> {
+    pub(crate) fn build_prange_vec(channel: RgbaChannels, info: &FlifInfo) -> Vec<ColorRange> {
         let mut prange = Vec::new();
 
         let transform = &info.transform;
@@ -308,17 +375,45 @@ impl<'a> ManiacTree<'a> {
             prange.push(transform.range(RgbaChannels::Green));
         }
 
-        if channel != RgbaChannels::Alpha && info.header.channels == ColorSpace::RGBA {
+        // images with a frame lookback always carry an alpha plane
+        let has_alpha =
+            info.header.channels == ColorSpace::RGBA || transform.max_lookback().is_some();
+        if (channel as usize) < 3 && has_alpha {
             prange.push(transform.range(RgbaChannels::Alpha));
         }
 
-        prange.push(transform.range(channel));
-        prange.push(ColorRange { min: 0, max: 2 });
-
         let maxdiff = ColorRange {
             min: transform.range(channel).min - transform.range(channel).max,
             max: transform.range(channel).max - transform.range(channel).min,
         };
+
+        if info.header.interlaced {
+            prange.push(ColorRange { min: 0, max: 2 });
+
+            if channel == RgbaChannels::Green || channel == RgbaChannels::Blue {
+                let luma = transform.range(RgbaChannels::Red);
+                prange.push(ColorRange {
+                    min: luma.min - luma.max,
+                    max: luma.max - luma.min,
+                });
+            }
+
+            prange.push(maxdiff);
+            prange.push(maxdiff);
+            prange.push(maxdiff);
+            prange.push(maxdiff);
+            prange.push(transform.range(channel));
+
+            if channel != RgbaChannels::Blue {
+                prange.push(maxdiff);
+                prange.push(maxdiff);
+            }
+
+            return prange;
+        }
+
+        prange.push(transform.range(channel));
+        prange.push(ColorRange { min: 0, max: 2 });
         prange.push(maxdiff);
         prange.push(maxdiff);
         prange.push(maxdiff);
@@ -330,19 +425,19 @@ impl<'a> ManiacTree<'a> {
 }
 
 #[derive(Clone)]
-enum ManiacNode<'a> {
+enum ManiacNode {
     /// Denotes a property node, property nodes are nodes that currently act as leaf nodes but will become inner nodes when their counter reaches zero
     Property {
         id: isize,
-        value: i16,
-        table: ChanceTable<'a>,
+        value: ColorValue,
+        table: ChanceTable,
         counter: u32,
         left: usize,
         right: usize,
     },
     InactiveProperty {
         id: isize,
-        value: i16,
+        value: ColorValue,
         counter: u32,
         left: usize,
         right: usize,
@@ -350,18 +445,18 @@ enum ManiacNode<'a> {
     /// Inner nodes are property nodes whose counters have reached zero. They no longer have a context associated with them.
     Inner {
         id: isize,
-        value: i16,
+        value: ColorValue,
         left: usize,
         right: usize,
     },
     /// Leaf nodes are nodes that can never become inner nodes
-    Leaf(ChanceTable<'a>),
+    Leaf(ChanceTable),
     InactiveLeaf,
 }
 
-impl<'a> ManiacNode<'a> {
+impl ManiacNode {
     // return type is temporary, will be some reasonable pixel value
-    pub fn activate(&mut self, table: ChanceTable<'a>) {
+    pub fn activate(&mut self, table: ChanceTable) {
         use self::ManiacNode::*;
         *self = match self {
             InactiveLeaf => Leaf(table),
@@ -375,7 +470,7 @@ impl<'a> ManiacNode<'a> {
                 id: *id,
                 value: *value,
                 counter: *counter,
-                table: table,
+                table,
                 left: *left,
                 right: *right,
             },
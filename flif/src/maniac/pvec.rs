@@ -1,7 +1,9 @@
-use crate::decoding_image::{CorePixelVicinity, EdgePixelVicinity};
+use crate::coding_image::{CorePixelVicinity, EdgePixelVicinity, InterlacedPixelVicinity};
+use crate::numbers::median3;
 use crate::pixels::{ColorSpace, ColorValue, Pixel};
 
 type Pvec = [ColorValue; 10];
+type InterlacedPvec = [ColorValue; 11];
 
 pub(crate) fn core_pvec<P: Pixel>(pred: ColorValue, pvic: &CorePixelVicinity<P>) -> Pvec {
     let mut pvec = [0; 10];
@@ -100,3 +102,61 @@ pub(crate) fn edge_pvec<P: Pixel>(pred: ColorValue, pvic: &EdgePixelVicinity<P>)
 
     pvec
 }
+
+pub(crate) fn interlaced_pvec<P: Pixel>(
+    pred: ColorValue,
+    pvic: &InterlacedPixelVicinity<P>,
+) -> InterlacedPvec {
+    let mut pvec = [0; 11];
+    let mut i = 0;
+
+    let chan = pvic.chan;
+    if let Some(val) = pvic.pixel.get_red_pvec(chan) {
+        pvec[i] = val;
+        i += 1;
+    }
+
+    if let Some(val) = pvic.pixel.get_green_pvec(chan) {
+        pvec[i] = val;
+        i += 1;
+    }
+
+    if let Some(val) = pvic.pixel.get_alpha_pvec(chan) {
+        pvec[i] = val;
+        i += 1;
+    }
+
+    let top = pvic.top;
+    let bottom = pvic.bottom;
+    let left = pvic.left;
+
+    // which of the candidates is their median, no matter the predictor in use
+    let avg = (top + bottom) >> 1;
+    let top_left_gradient = left + top - pvic.top_left;
+    let bottom_left_gradient = left + bottom - pvic.bottom_left;
+    pvec[i] = match median3(avg, top_left_gradient, bottom_left_gradient) {
+        median if median == avg => 0,
+        median if median == top_left_gradient => 1,
+        _ => 2,
+    };
+    i += 1;
+
+    if let Some(luma_miss) = pvic.luma_miss {
+        pvec[i] = luma_miss;
+        i += 1;
+    }
+
+    pvec[i] = top - bottom;
+    pvec[i + 1] = top - ((pvic.top_left + pvic.top_right) >> 1);
+    pvec[i + 2] = left - ((pvic.top_left + pvic.bottom_left) >> 1);
+    pvec[i + 3] = bottom - ((pvic.bottom_left + pvic.bottom_right) >> 1);
+    pvec[i + 4] = pred;
+
+    // the blue plane has no use for the second order differences
+    if pvic.pixel.get_green_pvec(chan).is_none() {
+        pvec[i + 5] = pvic.top2.map_or(0, |top2| top2 - top);
+        pvec[i + 6] = pvic.left2.map_or(0, |left2| left2 - left);
+    }
+
+    pvec
+}
@@ -1,19 +1,32 @@
-const EXP_TABLE: [u16; 8] = [1000, 1200, 1500, 1750, 2000, 2300, 2800, 2400];
+use std::sync::Arc;
 
-const MANT_TABLE: [u16; 8] = [1900, 1850, 1800, 1750, 1650, 1600, 1600, 2048];
+/// Number of exponent and mantissa entries, enough for the differences of 16 bit images
+const BITS: usize = 18;
+
+#[rustfmt::skip]
+const EXP_TABLE: [u16; BITS] = [
+    1000, 1200, 1500, 1750, 2000, 2300, 2800, 2400, 2300,
+    2048, 2048, 2048, 2048, 2048, 2048, 2048, 2048, 2048,
+];
+
+#[rustfmt::skip]
+const MANT_TABLE: [u16; BITS] = [
+    1900, 1850, 1800, 1750, 1650, 1600, 1600, 2048, 2048,
+    2048, 2048, 2048, 2048, 2048, 2048, 2048, 2048, 2048,
+];
 
-// This ChanceTable works only for 8 bit images
 #[derive(Debug, Clone)]
-pub struct ChanceTable<'a> {
-    zero: u16,           // ChanceTableEntry::Zero
-    sign: u16,           // ChanceTableEntry::Sign
-    exp_false: [u16; 8], // [Exp(0, false) ... Exp(7, false)]
-    exp_true: [u16; 8],  // [Exp(0, true) ... Exp(7, true)]
-    mant: [u16; 8],      // [Mant(0) ... Mant(7)]
-    updates: &'a UpdateTable,
+pub struct ChanceTable {
+    zero: u16,              // ChanceTableEntry::Zero
+    sign: u16,              // ChanceTableEntry::Sign
+    exp_false: [u16; BITS], // [Exp(0, false) ... Exp(17, false)]
+    exp_true: [u16; BITS],  // [Exp(0, true) ... Exp(17, true)]
+    mant: [u16; BITS],      // [Mant(0) ... Mant(17)]
+    /// Shared with the `UpdateTable` the table was created from, so that a table can outlive it
+    updates: Arc<[u16]>,
 }
 
-impl<'a> ChanceTable<'a> {
+impl ChanceTable {
     pub fn new(updates: &UpdateTable) -> ChanceTable {
         //let ext_table = HashMap::default();
 
@@ -24,7 +37,7 @@ impl<'a> ChanceTable<'a> {
             exp_true: EXP_TABLE,
             mant: MANT_TABLE,
             //ext_table,
-            updates,
+            updates: updates.updates.clone(),
         }
     }
 
@@ -33,10 +46,9 @@ impl<'a> ChanceTable<'a> {
         match entry {
             ChanceTableEntry::Zero => self.zero,
             ChanceTableEntry::Sign => self.sign,
-            ChanceTableEntry::Exp(v, false) if v < 8 => self.exp_false[usize::from(v)],
-            ChanceTableEntry::Exp(v, true) if v < 8 => self.exp_true[usize::from(v)],
-            ChanceTableEntry::Mant(v) if v < 8 => self.mant[usize::from(v)],
-            _ => unreachable!(),
+            ChanceTableEntry::Exp(v, false) => self.exp_false[usize::from(v)],
+            ChanceTableEntry::Exp(v, true) => self.exp_true[usize::from(v)],
+            ChanceTableEntry::Mant(v) => self.mant[usize::from(v)],
         }
     }
 
@@ -45,12 +57,11 @@ impl<'a> ChanceTable<'a> {
         let old_chance = match entry {
             ChanceTableEntry::Zero => &mut self.zero,
             ChanceTableEntry::Sign => &mut self.sign,
-            ChanceTableEntry::Exp(v, false) if v < 8 => &mut self.exp_false[usize::from(v)],
-            ChanceTableEntry::Exp(v, true) if v < 8 => &mut self.exp_true[usize::from(v)],
-            ChanceTableEntry::Mant(v) if v < 8 => &mut self.mant[usize::from(v)],
-            _ => unreachable!(),
+            ChanceTableEntry::Exp(v, false) => &mut self.exp_false[usize::from(v)],
+            ChanceTableEntry::Exp(v, true) => &mut self.exp_true[usize::from(v)],
+            ChanceTableEntry::Mant(v) => &mut self.mant[usize::from(v)],
         };
-        *old_chance = self.updates.next_chance(bit, *old_chance);
+        *old_chance = next_chance(&self.updates, bit, *old_chance);
     }
 }
 
@@ -64,7 +75,7 @@ pub enum ChanceTableEntry {
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct UpdateTable {
-    updates: Vec<u16>,
+    updates: Arc<[u16]>,
 }
 
 impl UpdateTable {
@@ -89,7 +100,7 @@ impl UpdateTable {
 
             if (old_chance != 0)
                 && ((old_chance as usize) < updates.len())
-                && new_chance <= max_chance as u16
+                && new_chance <= max_chance
             {
                 updates[old_chance as usize] = new_chance;
             }
@@ -101,7 +112,7 @@ impl UpdateTable {
 
         //fill in the rest of the table
         for old_chance in u16::from(cutoff)..(max_chance + 1) {
-            const MAX: u64 = 1 + ::std::u32::MAX as u64;
+            const MAX: u64 = 1 + u32::MAX as u64;
             if updates[old_chance as usize] != 0 {
                 continue;
             }
@@ -120,32 +131,39 @@ impl UpdateTable {
             updates[old_chance as usize] = new_chance as u16;
         }
 
-        UpdateTable { updates }
+        UpdateTable {
+            updates: updates.into(),
+        }
     }
 
     pub fn next_chance(&self, bit: bool, chance: u16) -> u16 {
-        if bit {
-            self.updates[chance as usize]
-        } else {
-            4096 - self.updates[(4096 - chance) as usize]
-        }
+        next_chance(&self.updates, bit, chance)
     }
 
     #[inline(always)]
     fn update_chance_accumulator(old: u64, alpha_divisor: u8) -> u64 {
-        const MAX: u64 = ::std::u32::MAX as u64;
-        let v = (MAX - old as u64 + 1) * (MAX / u64::from(alpha_divisor));
+        const MAX: u64 = u32::MAX as u64;
+        let v = (MAX - old + 1) * (MAX / u64::from(alpha_divisor));
         if v & 0xFFFF_FFFF > 0 {
-            ((v + 1) >> 32) as u64
+            (v + 1) >> 32
         } else {
-            (v >> 32) as u64
+            v >> 32
         }
     }
 }
 
+#[inline(always)]
+fn next_chance(updates: &[u16], bit: bool, chance: u16) -> u16 {
+    if bit {
+        updates[chance as usize]
+    } else {
+        4096 - updates[(4096 - chance) as usize]
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    #[cfg_attr(rustfmt, rustfmt_skip)]
+    #[rustfmt::skip]
     const UPDATE_TABLE_TRUE: [u16; 4096] = [ 0, 0, 217, 218, 219, 220, 221, 222, 223, 224, 225, 226, 227, 228, 229, 230, 231, 232, 233, 234, 235, 235, 236, 237, 238, 239, 240, 241, 242, 243, 244, 245, 246, 247, 248, 249, 250, 251, 252, 253, 253, 254, 255, 256, 257, 258, 259, 260, 261, 262, 263, 264, 265, 266, 267, 268, 269, 270, 271, 271, 272, 273, 274, 275, 276, 277, 278, 279, 280, 281, 282, 283, 284, 285, 286, 287, 288, 289, 289, 290, 291, 292, 293, 294, 295, 296, 297, 298, 299, 300, 301, 302, 303, 304, 305, 306, 307, 307, 308,
         309, 310, 311, 312, 313, 314, 315, 316, 317, 318, 319, 320, 321, 322, 323, 324, 325, 325, 326, 327, 328, 329, 330, 331, 332, 333, 334, 335, 336, 337, 338, 339, 340, 341, 342, 343, 343, 344, 345, 346, 347, 348, 349, 350, 351, 352, 353, 354, 355, 356, 357, 358, 359, 360, 361, 361, 362, 363, 364, 365, 366, 367, 368, 369, 370, 371, 372, 373, 374, 375, 376, 377, 378, 379, 379, 380, 381, 382, 383, 384, 385, 386, 387, 388, 389, 390, 391, 392, 393, 394, 395, 396, 397, 397, 398, 399, 400, 401, 402, 403,
         404, 405, 406, 407, 408, 409, 410, 411, 412, 413, 414, 415, 415, 416, 417, 418, 419, 420, 421, 422, 423, 424, 425, 426, 427, 428, 429, 430, 431, 432, 433, 433, 434, 435, 436, 437, 438, 439, 440, 441, 442, 443, 444, 445, 446, 447, 448, 449, 450, 451, 451, 452, 453, 454, 455, 456, 457, 458, 459, 460, 461, 462, 463, 464, 465, 466, 467, 468, 469, 469, 470, 471, 472, 473, 474, 475, 476, 477, 478, 479, 480, 481, 482, 483, 484, 485, 486, 487, 487, 488, 489, 490, 491, 492, 493, 494, 495, 496, 497, 498,
@@ -189,7 +207,7 @@ mod tests {
         4004, 4005, 4006, 4007, 4008, 4009, 4010, 4011, 4012, 4013, 4014, 4015, 4015, 4016, 4017, 4018, 4019, 4020, 4021, 4022, 4023, 4024, 4025, 4026, 4027, 4028, 4029, 4030, 4031, 4032, 4033, 4033, 4034, 4035, 4036, 4037, 4038, 4039, 4040, 4041, 4042, 4043, 4044, 4045, 4046, 4047, 4048, 4049, 4049, 4051, 4052, 4052, 4053, 4054, 4055, 4056, 4057, 4059, 4059, 4060, 4060, 4062, 4063, 4064, 4065, 4066, 4067, 4067, 4069, 4069, 4070, 4072, 4072, 4073, 4074, 4075, 4076, 4077, 4078, 4079, 4080, 4081, 4082, 4083, 4084, 4085, 4086, 4087, 4088, 4089, 4090, 4091, 4092, 4093, 4094, 4094, 0,
     ];
 
-    #[cfg_attr(rustfmt, rustfmt_skip)]
+    #[rustfmt::skip]
     const UPDATE_TABLE_FALSE: [u16; 4096] = [ 0, 4096, 2, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 24, 26, 27, 27, 29, 29, 30, 31, 32, 33, 34, 36, 36, 37, 37, 39, 40, 41, 42, 43, 44, 44, 45, 47, 47, 48, 49, 50, 51, 52, 53, 54, 55, 56, 57, 58, 59, 60, 61, 62, 63, 63, 64, 65, 66, 67, 68, 69, 70, 71, 72, 73, 74, 75, 76, 77, 78, 79, 80, 81, 81, 82, 83, 84, 85, 86, 87, 88, 89, 90, 91, 92, 93,
         94, 95, 96, 97, 98, 99, 99, 100, 101, 102, 103, 105, 105, 106, 107, 108, 109, 110, 110, 112, 113, 114, 115, 116, 117, 117, 118, 119, 120, 121, 122, 123, 124, 125, 126, 127, 128, 129, 130, 131, 132, 133, 134, 135, 135, 136, 137, 138, 139, 140, 141, 142, 143, 144, 145, 146, 147, 148, 149, 150, 151, 152, 153, 153, 154, 155, 156, 157, 158, 159, 160, 161, 162, 163, 164, 165, 166, 167, 168, 169, 170, 170, 171, 172, 173, 174, 175, 176, 177, 178, 179, 180, 181, 182, 183, 184, 185, 186, 187, 188,
         189, 190, 190, 191, 192, 193, 194, 195, 196, 197, 198, 199, 200, 201, 202, 203, 204, 205, 206, 207, 207, 208, 209, 210, 211, 212, 213, 214, 215, 216, 217, 218, 219, 220, 221, 222, 223, 223, 225, 225, 226, 227, 228, 229, 230, 231, 232, 233, 234, 235, 236, 237, 238, 239, 240, 241, 242, 243, 243, 244, 245, 246, 247, 249, 249, 250, 251, 252, 253, 254, 255, 256, 257, 258, 259, 260, 261, 261, 262, 263, 264, 265, 266, 267, 268, 269, 270, 271, 272, 273, 274, 275, 276, 277, 278, 279, 279, 280, 281, 282,
@@ -238,7 +256,7 @@ mod tests {
         use crate::numbers::chances::UpdateTable;
 
         let update_table = UpdateTable::new(19, 2);
-        assert_eq!(update_table.updates, UPDATE_TABLE_TRUE.as_ref());
+        assert_eq!(&update_table.updates[..], UPDATE_TABLE_TRUE.as_ref());
     }
 
     #[test]
@@ -246,9 +264,9 @@ mod tests {
         use crate::numbers::chances::UpdateTable;
 
         let update_table = UpdateTable::new(19, 2);
-        for i in 1..4096 {
+        for (i, &expected) in UPDATE_TABLE_FALSE.iter().enumerate().skip(1) {
             let new_chance = update_table.next_chance(false, i as u16);
-            assert_eq!(new_chance, UPDATE_TABLE_FALSE[i]);
+            assert_eq!(new_chance, expected);
         }
     }
 
@@ -293,5 +311,11 @@ mod tests {
 
         let r = MockRac.read_near_zero(0, 255, &mut table).unwrap();
         assert_eq!(r, 255);
+
+        // differences in 16 bit images need more than 8 exponent bits
+        let r = MockRac
+            .read_near_zero(-131_070, 131_070, &mut table)
+            .unwrap();
+        assert_eq!(r, 131_070);
     }
 }
@@ -1,5 +1,5 @@
 use std::cmp::{max, min};
-use std::io::Read;
+use std::io::{Read, Write};
 
 use crate::error::Result;
 
@@ -11,7 +11,7 @@ pub mod rac;
 pub mod symbol;
 pub mod varint;
 
-pub trait FlifReadExt {
+pub(crate) trait FlifReadExt {
     fn read_u8(&mut self) -> Result<u8>;
     fn read_varint<T: PrimInt + Unsigned>(&mut self) -> Result<T>;
 }
@@ -28,7 +28,23 @@ impl<R: Read> FlifReadExt for R {
     }
 }
 
+pub(crate) trait FlifWriteExt {
+    fn write_u8(&mut self, byte: u8) -> Result<()>;
+    fn write_varint<T: PrimInt + Unsigned>(&mut self, value: T) -> Result<()>;
+}
+
+impl<W: Write> FlifWriteExt for W {
+    fn write_u8(&mut self, byte: u8) -> Result<()> {
+        self.write_all(&[byte])?;
+        Ok(())
+    }
+
+    fn write_varint<T: PrimInt + Unsigned>(&mut self, value: T) -> Result<()> {
+        varint::write_varint(self, value)
+    }
+}
+
 #[inline(always)]
-pub fn median3<T: PrimInt>(a: T, b: T, c: T) -> T {
+pub(crate) fn median3<T: PrimInt>(a: T, b: T, c: T) -> T {
     max(min(a, b), min(max(a, b), c))
 }
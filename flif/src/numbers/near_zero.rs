@@ -1,11 +1,12 @@
 use crate::error::*;
 use crate::numbers::chances::{ChanceTable, ChanceTableEntry};
-use crate::numbers::rac::RacRead;
+use crate::numbers::rac::{RacRead, RacWrite};
 
 use num_traits::PrimInt;
 
 use std::cmp;
 
+/// Reads integers that are likely close to zero, using a context for every bit
 pub trait NearZeroCoder {
     fn read_near_zero<I: PrimInt>(
         &mut self,
@@ -29,6 +30,36 @@ impl<R: RacRead> NearZeroCoder for R {
     }
 }
 
+/// Writes integers the way `NearZeroCoder` reads them
+pub trait NearZeroWriter {
+    fn write_near_zero<I: PrimInt>(
+        &mut self,
+        min: I,
+        max: I,
+        value: I,
+        context: &mut ChanceTable,
+    ) -> Result<()>;
+}
+
+impl<W: RacWrite> NearZeroWriter for W {
+    fn write_near_zero<I: PrimInt>(
+        &mut self,
+        min: I,
+        max: I,
+        value: I,
+        context: &mut ChanceTable,
+    ) -> Result<()> {
+        if value < min || value > max {
+            return Err(Error::InvalidOperation(
+                "near zero integer writing was passed a value outside of min and max".into(),
+            ));
+        }
+
+        let delta = cmp::min(max, cmp::max(I::zero(), min));
+        write_near_zero_inner(self, min - delta, max - delta, value - delta, context)
+    }
+}
+
 #[inline(always)]
 fn read_near_zero_inner<R: RacRead, I: PrimInt>(
     read: &mut R,
@@ -107,11 +138,178 @@ fn read_near_zero_inner<R: RacRead, I: PrimInt>(
     Ok(if sign { have } else { (!have) + I::one() })
 }
 
+/// Mirrors `read_near_zero_inner`, every bit the reader can infer is left out
+#[inline(always)]
+fn write_near_zero_inner<W: RacWrite, I: PrimInt>(
+    write: &mut W,
+    min: I,
+    max: I,
+    value: I,
+    context: &mut ChanceTable,
+) -> Result<()> {
+    if min == max {
+        return Ok(());
+    }
+
+    write.write(context, ChanceTableEntry::Zero, value == I::zero())?;
+    if value == I::zero() {
+        return Ok(());
+    }
+
+    let sign = value > I::zero();
+    if min < I::zero() && max > I::zero() {
+        write.write(context, ChanceTableEntry::Sign, sign)?;
+    }
+
+    // see `read_near_zero_inner` on why the bitwise negation is safe
+    let absolute_max = if sign { max } else { (!min) + I::one() };
+    let absolute = if sign { value } else { (!value) + I::one() };
+    let absolute = absolute.to_u64().unwrap();
+
+    let largest_exponent =
+        (::std::mem::size_of::<I>() * 8) - absolute_max.leading_zeros() as usize - 1;
+    let exponent = 63 - absolute.leading_zeros() as usize;
+
+    for i in 0..exponent {
+        write.write(context, ChanceTableEntry::Exp(i as u8, sign), false)?;
+    }
+    if exponent != largest_exponent {
+        write.write(context, ChanceTableEntry::Exp(exponent as u8, sign), true)?;
+    }
+
+    // the first mantissa bit is always 1
+    let mut have = 1u64 << exponent;
+    for pos in (0..exponent).rev() {
+        let minabs1 = have | (1 << pos);
+        // the 1-bit is only written if it wouldn't bump the value above the maximum
+        if I::from(minabs1).is_none_or(|minabs1| minabs1 > absolute_max) {
+            continue;
+        }
+
+        let bit = absolute & (1 << pos) != 0;
+        write.write(context, ChanceTableEntry::Mant(pos as u8), bit)?;
+        if bit {
+            have = minabs1;
+        }
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
+    use super::{NearZeroCoder, NearZeroWriter};
+    use crate::numbers::chances::{ChanceTable, UpdateTable};
+    use crate::numbers::rac::{Rac, RacWrite};
+
+    /// Writes every `(min, max, value)` with a single context and checks that they read back
+    fn round_trip(values: &[(i32, i32, i32)]) {
+        let update_table = UpdateTable::new(19, 2);
+
+        let mut buf: Vec<u8> = vec![];
+        {
+            let mut context = ChanceTable::new(&update_table);
+            let mut rac = Rac::from_writer(&mut buf);
+            for &(min, max, value) in values {
+                rac.write_near_zero(min, max, value, &mut context).unwrap();
+            }
+            rac.flush().unwrap();
+        }
+
+        let mut context = ChanceTable::new(&update_table);
+        let mut rac = Rac::from_reader(buf.as_slice()).unwrap();
+        for &(min, max, value) in values {
+            let read = rac.read_near_zero(min, max, &mut context).unwrap();
+            assert_eq!(value, read, "range {}..={}", min, max);
+        }
+    }
+
     #[test]
     fn test_bitwise_negation() {
         let n = -6;
         assert_eq!(6, !n + 1);
     }
+
+    #[test]
+    fn test_near_zero_all_small_ranges() {
+        let mut values = Vec::new();
+        for min in -40..=40 {
+            for max in min..=40 {
+                values.extend((min..=max).map(|value| (min, max, value)));
+            }
+        }
+        round_trip(&values);
+    }
+
+    #[test]
+    fn test_near_zero_boundary_exponents() {
+        let mut values = Vec::new();
+        // the chance tables have room for exponents up to 17
+        for exp in 0..17 {
+            let power = 1 << exp;
+            // ranges ending just below, at and just above a power of two, so the largest
+            // exponent and the skipped mantissa bits are covered
+            for &bound in &[power - 1, power, power + 1] {
+                for &value in &[1, power - 1, power, power + 1, bound - 1, bound] {
+                    if value >= 1 && value <= bound {
+                        values.push((0, bound, value));
+                        values.push((-bound, 0, -value));
+                        values.push((-bound, bound, value));
+                        values.push((-bound, bound, -value));
+                    }
+                }
+                values.push((-bound, bound, 0));
+            }
+        }
+        let largest = (1 << 18) - 1;
+        values.push((-largest, largest, largest));
+        values.push((-largest, largest, -largest));
+        round_trip(&values);
+    }
+
+    #[test]
+    fn test_near_zero_ranges_without_zero() {
+        // ranges that don't contain zero are shifted so their closest value to zero becomes 0
+        let values = [
+            (5, 9, 5),
+            (5, 9, 9),
+            (-9, -5, -5),
+            (-9, -5, -9),
+            (1000, 100_000, 65_536),
+            (-100_000, -1000, -65_537),
+            (7, 7, 7),
+        ];
+        round_trip(&values);
+    }
+
+    #[test]
+    fn test_near_zero_unsigned() {
+        let update_table = UpdateTable::new(19, 2);
+
+        let mut buf: Vec<u8> = vec![];
+        {
+            let mut context = ChanceTable::new(&update_table);
+            let mut rac = Rac::from_writer(&mut buf);
+            for value in 0..=300usize {
+                rac.write_near_zero(0, 300, value, &mut context).unwrap();
+            }
+            rac.flush().unwrap();
+        }
+
+        let mut context = ChanceTable::new(&update_table);
+        let mut rac = Rac::from_reader(buf.as_slice()).unwrap();
+        for value in 0..=300usize {
+            assert_eq!(value, rac.read_near_zero(0, 300, &mut context).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_near_zero_write_out_of_range() {
+        let update_table = UpdateTable::new(19, 2);
+        let mut context = ChanceTable::new(&update_table);
+        let mut rac = Rac::from_writer(Vec::new());
+        assert!(rac.write_near_zero(-3, 3, 4, &mut context).is_err());
+        assert!(rac.write_near_zero(-3, 3, -4, &mut context).is_err());
+        assert!(rac.write_near_zero(3, -3, 0, &mut context).is_err());
+    }
 }
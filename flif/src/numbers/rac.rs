@@ -1,23 +1,48 @@
 use std::io;
-use std::io::Read;
-#[cfg(test)]
-use std::io::Write;
+use std::io::{Read, Write};
 
 use super::FlifReadExt;
 use crate::error::*;
 use crate::numbers::chances::{ChanceTable, ChanceTableEntry};
 
+/// Decoding side of the range coder
 pub trait RacRead {
+    /// Reads a bit with a 50% chance
     fn read_bit(&mut self) -> Result<bool>;
+    /// Reads a bit that is set with a chance of `chance / 4096`
     fn read_chance(&mut self, chance: u32) -> Result<bool>;
+    /// Reads a bit with the chance of `entry` in `context` and updates that chance
     fn read(&mut self, context: &mut ChanceTable, entry: ChanceTableEntry) -> Result<bool>;
 }
 
+/// Encoding side of the range coder, every method writes what the `RacRead` method of the same
+/// name reads back
+pub trait RacWrite {
+    fn write_bit(&mut self, bit: bool) -> Result<()>;
+    fn write_chance(&mut self, chance: u32, bit: bool) -> Result<()>;
+    fn write(
+        &mut self,
+        context: &mut ChanceTable,
+        entry: ChanceTableEntry,
+        bit: bool,
+    ) -> Result<()>;
+    /// Writes out the remaining state, has to be called once after the last bit
+    fn flush(&mut self) -> Result<()>;
+}
+
+/// Range coder reading from or writing to `RW`
+
 #[derive(Debug)]
 pub struct Rac<RW> {
-    reader: RW,
+    io: RW,
     range: u32,
     low: u32,
+    /// Last byte produced while writing, held back until it is known if a carry reaches it
+    delayed_byte: Option<u8>,
+    /// Number of 0xFF bytes produced after the delayed byte, a carry would turn them into 0x00
+    running_ffs: usize,
+    /// Set once reading ran past the end of the input
+    truncated: bool,
 }
 
 impl<RW> Rac<RW> {
@@ -43,6 +68,26 @@ impl<RW> Rac<RW> {
         let upper_bits = (range / 4096) * chance;
         upper_bits + lower_12bits
     }
+
+    pub(crate) fn get_ref(&self) -> &RW {
+        &self.io
+    }
+
+    pub(crate) fn get_mut(&mut self) -> &mut RW {
+        &mut self.io
+    }
+
+    /// Returns the reader or writer, a writer has to be flushed before this
+    pub fn into_inner(self) -> RW {
+        self.io
+    }
+}
+
+/// Position of a reading range coder within its input, see `Rac::rewind`
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct RacState {
+    range: u32,
+    low: u32,
 }
 
 impl<R: Read> RacRead for Rac<R> {
@@ -69,26 +114,58 @@ impl<R: Read> RacRead for Rac<R> {
 }
 
 impl<R: Read> Rac<R> {
-    pub fn from_reader(mut reader: R) -> Result<Rac<R>> {
+    pub fn from_reader(reader: R) -> Result<Rac<R>> {
+        let mut rac = Rac {
+            io: reader,
+            range: Self::MAX_RANGE,
+            low: 0,
+            delayed_byte: None,
+            running_ffs: 0,
+            truncated: false,
+        };
+
         // calculate the number of iterations needed to calculate low. The number of iterations
         // should be Self::MAX_RANGE_BITS / 8 rounded up
-        let needed_iterations =
-            (Self::MAX_RANGE_BITS / 8) + if Self::MAX_RANGE_BITS % 8 > 0 { 1 } else { 0 };
-
-        let low = (0..needed_iterations).fold(Ok(0), |acc: Result<u32>, _| {
-            let or_val = match reader.read_u8() {
-                Ok(val) => val,
-                Err(Error::Io(ref io)) if io.kind() == io::ErrorKind::UnexpectedEof => 0xFF,
-                err => err?,
-            };
-            acc.map(|acc| (acc << 8) | u32::from(or_val))
-        })?;
-
-        Ok(Rac {
-            reader,
-            range: Self::MAX_RANGE,
-            low,
-        })
+        let needed_iterations = Self::MAX_RANGE_BITS.div_ceil(8);
+        for _ in 0..needed_iterations {
+            rac.low = (rac.low << 8) | u32::from(rac.next_byte()?);
+        }
+
+        Ok(rac)
+    }
+
+    /// Whether the input ended before everything read so far was available. Every bit read
+    /// before that happened was decoded from the real input.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    pub(crate) fn state(&self) -> RacState {
+        RacState {
+            range: self.range,
+            low: self.low,
+        }
+    }
+
+    /// Goes back to an earlier `state`, the reader has to be moved back to where it was at
+    /// that point as well
+    pub(crate) fn rewind(&mut self, state: RacState) {
+        self.range = state.range;
+        self.low = state.low;
+        self.truncated = false;
+    }
+
+    /// Reads the next byte, a truncated input reads as 0xFF like it does in the reference
+    /// decoder so that partial files still decode
+    fn next_byte(&mut self) -> Result<u8> {
+        match self.io.read_u8() {
+            Ok(val) => Ok(val),
+            Err(Error::Io(ref io)) if io.kind() == io::ErrorKind::UnexpectedEof => {
+                self.truncated = true;
+                Ok(0xFF)
+            }
+            Err(err) => Err(err),
+        }
     }
 
     fn input(&mut self) -> Result<()> {
@@ -96,12 +173,7 @@ impl<R: Read> Rac<R> {
             if self.range <= Self::MIN_RANGE {
                 self.low <<= 8;
                 self.range <<= 8;
-
-                self.low |= u32::from(match self.reader.read_u8() {
-                    Ok(val) => val,
-                    Err(Error::Io(ref io)) if io.kind() == io::ErrorKind::UnexpectedEof => 0xFF,
-                    err => err?,
-                });
+                self.low |= u32::from(self.next_byte()?);
             }
         }
         Ok(())
@@ -126,59 +198,105 @@ impl<R: Read> Rac<R> {
     }
 }
 
-#[cfg(test)]
+impl<W: Write> RacWrite for Rac<W> {
+    fn write_bit(&mut self, bit: bool) -> Result<()> {
+        let chance = self.range >> 1;
+        self.set(chance, bit)
+    }
+
+    fn write_chance(&mut self, chance: u32, bit: bool) -> Result<()> {
+        let chance = Self::apply_chance(chance, self.range);
+        self.set(chance, bit)
+    }
+
+    #[inline(always)]
+    fn write(
+        &mut self,
+        context: &mut ChanceTable,
+        entry: ChanceTableEntry,
+        bit: bool,
+    ) -> Result<()> {
+        let chance = context.get_chance(entry);
+        let transformed_chance = Self::apply_chance(u32::from(chance), self.range);
+        self.set(transformed_chance, bit)?;
+        context.update_entry(bit, entry);
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.low += Self::MIN_RANGE - 1;
+        for _ in 0..4 {
+            self.range = Self::MIN_RANGE - 1;
+            self.output()?;
+        }
+
+        Ok(())
+    }
+}
+
 impl<W: Write> Rac<W> {
     pub fn from_writer(writer: W) -> Rac<W> {
         Rac {
-            low: 0,
+            io: writer,
             range: Self::MAX_RANGE,
-            reader: writer,
+            low: 0,
+            delayed_byte: None,
+            running_ffs: 0,
+            truncated: false,
         }
     }
 
     fn output(&mut self) -> Result<()> {
-        if self.range <= Self::MIN_RANGE {
-            // write out the top 8 bits of low
-            let byte = (self.low >> Self::MIN_RANGE_BITS) as u8;
-            self.reader.write_all(&[byte])?;
-            self.low <<= 8;
+        while self.range <= Self::MIN_RANGE {
+            // the top 8 bits of low, with a possible carry in the 9th bit
+            let byte = self.low >> Self::MIN_RANGE_BITS;
+            match self.delayed_byte {
+                None => self.delayed_byte = Some(byte as u8),
+                Some(delayed) if (self.low + self.range) >> 8 < Self::MIN_RANGE => {
+                    // no carry can reach the delayed byte anymore
+                    self.io.write_all(&[delayed])?;
+                    self.write_running(0xFF)?;
+                    self.delayed_byte = Some(byte as u8);
+                }
+                Some(delayed) if self.low >> 8 >= Self::MIN_RANGE => {
+                    // a carry certainly reaches the delayed byte
+                    self.io.write_all(&[delayed.wrapping_add(1)])?;
+                    self.write_running(0x00)?;
+                    self.delayed_byte = Some(byte as u8);
+                }
+                // a carry may still happen, so hold back another byte
+                Some(_) => self.running_ffs += 1,
+            }
+
+            self.low = (self.low & (Self::MIN_RANGE - 1)) << 8;
             self.range <<= 8;
         }
 
         Ok(())
     }
 
-    fn set(&mut self, chance: u32, bit: bool) -> Result<()> {
-        if bit {
-            self.low += self.range - chance;
-            self.range = chance;
-            self.output()?;
-        } else {
-            self.range -= chance;
-            self.output()?;
+    fn write_running(&mut self, byte: u8) -> Result<()> {
+        for _ in 0..self.running_ffs {
+            self.io.write_all(&[byte])?;
         }
+        self.running_ffs = 0;
 
         Ok(())
     }
 
-    pub fn write_bit(&mut self, bit: bool) -> Result<()> {
-        let chance = self.range >> 1;
-        self.set(chance, bit)
-    }
-
-    pub fn write_chance(&mut self, chance: u32, bit: bool) -> Result<()> {
-        let chance = Self::apply_chance(chance, self.range);
-        self.set(chance, bit)
-    }
+    fn set(&mut self, chance: u32, bit: bool) -> Result<()> {
+        // the reader asserts the same, so a failure here would produce an unreadable stream
+        assert!(chance > 0 && chance < self.range);
 
-    pub fn flush(&mut self) -> Result<()> {
-        // flush is only ever required if there is data in the top 8 bits (out of 24) of low.
-        if self.low >> Self::MIN_RANGE_BITS > 0 {
-            let byte = (self.low >> Self::MIN_RANGE_BITS) as u8;
-            self.reader.write_all(&[byte])?;
+        if bit {
+            self.low += self.range - chance;
+            self.range = chance;
+        } else {
+            self.range -= chance;
         }
 
-        Ok(())
+        self.output()
     }
 }
 
@@ -197,7 +315,7 @@ mod tests {
 
     #[test]
     fn test_rac_bidirectional_chance() {
-        use crate::numbers::rac::{Rac, RacRead};
+        use crate::numbers::rac::{Rac, RacRead, RacWrite};
 
         let mut buf: Vec<u8> = vec![];
         {
@@ -217,7 +335,7 @@ mod tests {
 
     #[test]
     fn test_rac_bidirectional_bits() {
-        use crate::numbers::rac::{Rac, RacRead};
+        use crate::numbers::rac::{Rac, RacRead, RacWrite};
 
         let mut buf: Vec<u8> = vec![];
         {
@@ -234,4 +352,40 @@ mod tests {
             assert_eq!(bit, reader_rac.read_bit().unwrap());
         }
     }
+
+    #[test]
+    fn test_rac_bidirectional_context() {
+        use crate::numbers::chances::{ChanceTable, ChanceTableEntry, UpdateTable};
+        use crate::numbers::rac::{Rac, RacRead, RacWrite};
+
+        // long runs skew the chances towards the cutoff, so the updates matter
+        let bits: Vec<bool> = (0..2000u32).map(|i| i % 7 == 0 || i > 1500).collect();
+        let entries = [
+            ChanceTableEntry::Zero,
+            ChanceTableEntry::Sign,
+            ChanceTableEntry::Exp(3, true),
+            ChanceTableEntry::Mant(5),
+        ];
+        let update_table = UpdateTable::new(19, 2);
+
+        let mut buf: Vec<u8> = vec![];
+        {
+            let mut context = ChanceTable::new(&update_table);
+            let mut writer_rac = Rac::from_writer(&mut buf);
+            for (i, &bit) in bits.iter().enumerate() {
+                writer_rac
+                    .write(&mut context, entries[i % entries.len()], bit)
+                    .unwrap();
+            }
+            writer_rac.flush().unwrap();
+        }
+
+        let mut context = ChanceTable::new(&update_table);
+        let read_buf: &[u8] = buf.as_ref();
+        let mut reader_rac = Rac::from_reader(read_buf).unwrap();
+        for (i, &bit) in bits.iter().enumerate() {
+            let entry = entries[i % entries.len()];
+            assert_eq!(bit, reader_rac.read(&mut context, entry).unwrap());
+        }
+    }
 }
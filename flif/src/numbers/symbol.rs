@@ -1,8 +1,9 @@
-use super::rac::RacRead;
+use super::rac::{RacRead, RacWrite};
 use crate::error::*;
 
 use num_traits::PrimInt;
 
+/// Reads uniformly distributed integers and booleans
 pub trait UniformSymbolCoder {
     fn read_val<T: PrimInt>(&mut self, min: T, max: T) -> Result<T>;
     fn read_bool(&mut self) -> Result<bool>;
@@ -23,6 +24,100 @@ impl<R: RacRead> UniformSymbolCoder for R {
     }
 
     fn read_bool(&mut self) -> Result<bool> {
-        Ok(self.read_bit()?)
+        self.read_bit()
+    }
+}
+
+/// Writes integers and booleans the way `UniformSymbolCoder` reads them
+pub trait UniformSymbolWriter {
+    fn write_val<T: PrimInt>(&mut self, min: T, max: T, value: T) -> Result<()>;
+    fn write_bool(&mut self, value: bool) -> Result<()>;
+}
+
+impl<W: RacWrite> UniformSymbolWriter for W {
+    fn write_val<T: PrimInt>(&mut self, mut min: T, mut max: T, value: T) -> Result<()> {
+        if value < min || value > max {
+            return Err(Error::InvalidOperation(
+                "uniform symbol writing was passed a value outside of min and max".into(),
+            ));
+        }
+
+        while max != min {
+            let mid = min + ((max - min) >> 1);
+            if value > mid {
+                self.write_bit(true)?;
+                min = mid + T::one();
+            } else {
+                self.write_bit(false)?;
+                max = mid;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_bool(&mut self, value: bool) -> Result<()> {
+        self.write_bit(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{UniformSymbolCoder, UniformSymbolWriter};
+    use crate::numbers::rac::{Rac, RacWrite};
+
+    #[test]
+    fn test_uniform_symbol_round_trip() {
+        let mut values = Vec::new();
+        for min in -20..=20 {
+            for max in min..=20 {
+                values.extend((min..=max).map(|value| (min, max, value)));
+            }
+        }
+        // the difference between min and max has to fit the type
+        let (min, max) = (i32::MIN / 2, i32::MAX / 2);
+        values.extend_from_slice(&[(min, max, min), (min, max, max), (min, max, -1)]);
+
+        let mut buf: Vec<u8> = vec![];
+        {
+            let mut rac = Rac::from_writer(&mut buf);
+            for &(min, max, value) in &values {
+                rac.write_val(min, max, value).unwrap();
+                rac.write_bool(value % 2 == 0).unwrap();
+            }
+            rac.flush().unwrap();
+        }
+
+        let mut rac = Rac::from_reader(buf.as_slice()).unwrap();
+        for &(min, max, value) in &values {
+            assert_eq!(value, rac.read_val(min, max).unwrap());
+            assert_eq!(value % 2 == 0, rac.read_bool().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_uniform_symbol_unsigned() {
+        let mut buf: Vec<u8> = vec![];
+        {
+            let mut rac = Rac::from_writer(&mut buf);
+            for value in 0..=255u8 {
+                rac.write_val(0, 255, value).unwrap();
+                rac.write_val(value, 255, 255).unwrap();
+            }
+            rac.flush().unwrap();
+        }
+
+        let mut rac = Rac::from_reader(buf.as_slice()).unwrap();
+        for value in 0..=255u8 {
+            assert_eq!(value, rac.read_val(0, 255).unwrap());
+            assert_eq!(255, rac.read_val(value, 255).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_uniform_symbol_write_out_of_range() {
+        let mut rac = Rac::from_writer(Vec::new());
+        assert!(rac.write_val(0, 10, 11).is_err());
+        assert!(rac.write_val(-10, -1, 0).is_err());
     }
 }
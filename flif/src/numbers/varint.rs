@@ -1,10 +1,11 @@
-use std::io::Read;
+use std::io::{Read, Write};
 
 use super::FlifReadExt;
 use crate::error::{Error, Result};
 
 use num_traits::{PrimInt, Unsigned};
 
+/// Reads a big endian integer made up of 7 bit groups, the high bit marks all but the last group
 // T::from(_).unwrap() is panic-safe in this function because there exists no type that is both
 // PrimInt and Unsigned that cannot store a u8
 pub fn read_varint<R: Read, T: PrimInt + Unsigned>(mut reader: R) -> Result<T> {
@@ -31,6 +32,40 @@ pub fn read_varint<R: Read, T: PrimInt + Unsigned>(mut reader: R) -> Result<T> {
     }
 }
 
+/// Writes an integer the way `read_varint` reads it
+pub fn write_varint<W: Write, T: PrimInt + Unsigned>(mut writer: W, value: T) -> Result<()> {
+    let mask = T::from(0b0111_1111).unwrap();
+
+    // collect the 7 bit groups from least to most significant
+    let mut groups = Vec::with_capacity(::std::mem::size_of::<T>() * 8 / 7 + 1);
+    let mut value = value;
+    loop {
+        groups.push((value & mask).to_u8().unwrap());
+        value = value >> 7;
+        if value.is_zero() {
+            break;
+        }
+    }
+
+    // every group but the last one has the continuation bit set
+    let last = groups.len() - 1;
+    let bytes: Vec<u8> = groups
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &group)| {
+            if i == last {
+                group
+            } else {
+                group | 0b1000_0000
+            }
+        })
+        .collect();
+    writer.write_all(&bytes)?;
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     #[test]
@@ -51,7 +86,7 @@ mod tests {
 
         let buf = [0x8F, 0xFF, 0xFF, 0xFF, 0x7F];
         let num: u32 = buf.as_ref().read_varint().unwrap();
-        assert_eq!(num, u32::max_value());
+        assert_eq!(num, u32::MAX);
     }
 
     #[test]
@@ -60,7 +95,7 @@ mod tests {
 
         let buf = [0x00];
         let num: u32 = buf.as_ref().read_varint().unwrap();
-        assert_eq!(num, u32::min_value());
+        assert_eq!(num, u32::MIN);
     }
 
     #[test]
@@ -76,4 +111,48 @@ mod tests {
             ::std::mem::discriminant(&Error::InvalidVarint)
         )
     }
+
+    #[test]
+    fn test_varint_write() {
+        use crate::numbers::FlifWriteExt;
+
+        let mut buf = Vec::new();
+        buf.write_varint(351u32).unwrap();
+        buf.write_varint(303u32).unwrap();
+        buf.write_varint(0u32).unwrap();
+        buf.write_varint(u32::MAX).unwrap();
+        assert_eq!(
+            buf,
+            [0x82, 0x5F, 0x82, 0x2F, 0x00, 0x8F, 0xFF, 0xFF, 0xFF, 0x7F]
+        );
+    }
+
+    #[test]
+    fn test_varint_round_trip() {
+        use crate::numbers::{FlifReadExt, FlifWriteExt};
+
+        // every group boundary, plus the values around it
+        let mut values = vec![0u64, u64::MAX];
+        for shift in (7..64).step_by(7) {
+            let boundary = 1u64 << shift;
+            values.extend_from_slice(&[boundary - 1, boundary, boundary + 1]);
+        }
+
+        let mut buf = Vec::new();
+        for &value in &values {
+            buf.write_varint(value).unwrap();
+        }
+        for value in 0..=u16::MAX {
+            buf.write_varint(value).unwrap();
+        }
+
+        let mut reader = buf.as_slice();
+        for &value in &values {
+            assert_eq!(value, reader.read_varint::<u64>().unwrap());
+        }
+        for value in 0..=u16::MAX {
+            assert_eq!(value, reader.read_varint::<u16>().unwrap());
+        }
+        assert!(reader.is_empty());
+    }
 }
@@ -1,4 +1,4 @@
-pub type ColorValue = i16;
+pub type ColorValue = i32;
 
 #[derive(PartialEq, Eq, Debug, Clone, Copy)]
 pub enum ColorSpace {
@@ -9,14 +9,12 @@ pub enum ColorSpace {
 
 pub trait ChannelsTrait {
     fn as_channel(&self) -> RgbaChannels;
-    fn is_alpha(&self) -> bool;
 }
 
 pub trait Pixel: Default + Copy {
     type Channels: ChannelsTrait + Copy;
     type ChanOrder: AsRef<[Self::Channels]>;
 
-    fn is_rgba() -> bool;
     fn get_value(&self, chan: Self::Channels) -> ColorValue;
     fn set_value(&mut self, val: ColorValue, chan: Self::Channels);
     /// Return if alpha channel equals to zero. For non-RGBA images always
@@ -31,12 +29,17 @@ pub trait Pixel: Default + Copy {
     fn get_alpha_pvec(&self, chan: Self::Channels) -> Option<ColorValue>;
 
     fn to_rgba(&self) -> Rgba;
+    /// Return how many frames back this pixel is copied from. Always 0 for images
+    /// without a lookback plane.
+    #[inline(always)]
+    fn get_lookback(&self) -> ColorValue {
+        0
+    }
     fn get_chan_order() -> Self::ChanOrder;
     #[inline(always)]
     fn maniac_init_order() -> Self::ChanOrder {
         Self::get_chan_order()
     }
-    fn size() -> usize;
 }
 
 #[derive(Debug, Default, Copy, Clone)]
@@ -52,20 +55,12 @@ impl ChannelsTrait for GreyChannels {
     fn as_channel(&self) -> RgbaChannels {
         RgbaChannels::Red
     }
-    #[inline(always)]
-    fn is_alpha(&self) -> bool {
-        false
-    }
 }
 
 impl Pixel for Greyscale {
     type Channels = GreyChannels;
     type ChanOrder = [GreyChannels; 1];
 
-    #[inline(always)]
-    fn is_rgba() -> bool {
-        false
-    }
     #[inline(always)]
     fn get_value(&self, _chan: Self::Channels) -> ColorValue {
         self.0
@@ -98,10 +93,6 @@ impl Pixel for Greyscale {
     fn get_chan_order() -> Self::ChanOrder {
         [GreyChannels::Grey]
     }
-    #[inline(always)]
-    fn size() -> usize {
-        1
-    }
 }
 
 #[derive(Debug, Default, Copy, Clone)]
@@ -123,20 +114,12 @@ impl ChannelsTrait for RgbChannels {
             RgbChannels::Blue => RgbaChannels::Blue,
         }
     }
-    #[inline(always)]
-    fn is_alpha(&self) -> bool {
-        false
-    }
 }
 
 impl Pixel for Rgb {
     type Channels = RgbChannels;
     type ChanOrder = [RgbChannels; 3];
 
-    #[inline(always)]
-    fn is_rgba() -> bool {
-        false
-    }
     #[inline(always)]
     fn get_value(&self, chan: Self::Channels) -> ColorValue {
         self.0[chan as usize]
@@ -177,10 +160,6 @@ impl Pixel for Rgb {
     fn get_chan_order() -> Self::ChanOrder {
         [RgbChannels::Red, RgbChannels::Green, RgbChannels::Blue]
     }
-    #[inline(always)]
-    fn size() -> usize {
-        3
-    }
 }
 
 #[derive(Debug, Default, Copy, Clone)]
@@ -192,6 +171,8 @@ pub enum RgbaChannels {
     Green = 1,
     Blue = 2,
     Alpha = 3,
+    /// Frame lookback plane, only present in animations using the `FrameLookback` transformation
+    Lookback = 4,
 }
 
 impl RgbaChannels {
@@ -209,20 +190,12 @@ impl ChannelsTrait for RgbaChannels {
     fn as_channel(&self) -> RgbaChannels {
         *self
     }
-    #[inline(always)]
-    fn is_alpha(&self) -> bool {
-        *self == RgbaChannels::Alpha
-    }
 }
 
 impl Pixel for Rgba {
     type Channels = RgbaChannels;
     type ChanOrder = [RgbaChannels; 4];
 
-    #[inline(always)]
-    fn is_rgba() -> bool {
-        true
-    }
     #[inline(always)]
     fn get_value(&self, chan: Self::Channels) -> ColorValue {
         self.0[chan as usize]
@@ -281,8 +254,109 @@ impl Pixel for Rgba {
             RgbaChannels::Alpha,
         ]
     }
+}
+
+/// Pixel of an animation using the `FrameLookback` transformation, images without alpha
+/// get a constant alpha plane
+#[derive(Debug, Default, Copy, Clone)]
+pub struct RgbaLookback([ColorValue; 5]);
+
+impl Pixel for RgbaLookback {
+    type Channels = RgbaChannels;
+    type ChanOrder = [RgbaChannels; 5];
+
+    #[inline(always)]
+    fn get_value(&self, chan: Self::Channels) -> ColorValue {
+        self.0[chan as usize]
+    }
+    #[inline(always)]
+    fn set_value(&mut self, val: ColorValue, chan: Self::Channels) {
+        self.0[chan as usize] = val;
+    }
+    #[inline(always)]
+    fn is_alpha_zero(&self) -> bool {
+        self.0[3] == 0
+    }
+    #[inline(always)]
+    fn get_red_pvec(&self, chan: Self::Channels) -> Option<ColorValue> {
+        if chan == RgbaChannels::Green || chan == RgbaChannels::Blue {
+            Some(self.0[0])
+        } else {
+            None
+        }
+    }
+    #[inline(always)]
+    fn get_green_pvec(&self, chan: Self::Channels) -> Option<ColorValue> {
+        if chan == RgbaChannels::Blue {
+            Some(self.0[1])
+        } else {
+            None
+        }
+    }
+    #[inline(always)]
+    fn get_alpha_pvec(&self, chan: Self::Channels) -> Option<ColorValue> {
+        if (chan as usize) < 3 {
+            Some(self.0[3])
+        } else {
+            None
+        }
+    }
+    #[inline(always)]
+    fn to_rgba(&self) -> Rgba {
+        Rgba([self.0[0], self.0[1], self.0[2], self.0[3]])
+    }
+    #[inline(always)]
+    fn get_lookback(&self) -> ColorValue {
+        self.0[4]
+    }
+    #[inline(always)]
+    fn get_chan_order() -> Self::ChanOrder {
+        [
+            RgbaChannels::Lookback,
+            RgbaChannels::Alpha,
+            RgbaChannels::Red,
+            RgbaChannels::Green,
+            RgbaChannels::Blue,
+        ]
+    }
     #[inline(always)]
-    fn size() -> usize {
-        4
+    fn maniac_init_order() -> Self::ChanOrder {
+        [
+            RgbaChannels::Red,
+            RgbaChannels::Green,
+            RgbaChannels::Blue,
+            RgbaChannels::Alpha,
+            RgbaChannels::Lookback,
+        ]
     }
 }
+
+/// 8 bit pixel of a decoded greyscale image
+#[repr(transparent)]
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct Grey8(pub u8);
+
+/// 8 bit pixel of a decoded RGB image
+#[repr(transparent)]
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct Rgb8(pub [u8; 3]);
+
+/// 8 bit pixel of a decoded RGBA image
+#[repr(transparent)]
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct Rgba8(pub [u8; 4]);
+
+/// Pixel of a decoded greyscale image with more than 8 bits per channel
+#[repr(transparent)]
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct Grey16(pub u16);
+
+/// Pixel of a decoded RGB image with more than 8 bits per channel
+#[repr(transparent)]
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct Rgb16(pub [u16; 3]);
+
+/// Pixel of a decoded RGBA image with more than 8 bits per channel
+#[repr(transparent)]
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct Rgba16(pub [u16; 4]);
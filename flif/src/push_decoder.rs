@@ -0,0 +1,504 @@
+use std::io;
+use std::io::Read;
+use std::mem;
+
+use num_traits::PrimInt;
+
+use super::{Flif, FlifInfo, Frame, Metadata};
+use crate::coding_image::{Advance, CodingImage, ManiacForest, PixelCoder, Position};
+use crate::components::header::{Header, SecondHeader};
+use crate::components::transformations::ColorRange;
+use crate::error::*;
+use crate::frame::FrameData;
+use crate::maniac::ManiacTree;
+use crate::numbers::chances::{ChanceTable, UpdateTable};
+use crate::numbers::rac::{Rac, RacState};
+use crate::pixels::{ColorSpace, ColorValue, RgbaChannels};
+use crate::pixels::{Greyscale, Pixel, Rgb, Rgba, RgbaLookback};
+use crate::Limits;
+
+/// How far a `PushDecoder` got with the input it was fed so far
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Progress {
+    /// The main header is incomplete
+    Header,
+    /// The metadata chunks are incomplete
+    Metadata,
+    /// The second header, which holds the transformations, is incomplete
+    SecondHeader,
+    /// The MANIAC trees of a non-interlaced image are incomplete
+    Trees,
+    /// The first rows of every frame are decoded. Interlaced images complete all of their
+    /// rows in the final pass, use `Decoder::decode_preview` to show them before that.
+    Rows(u32),
+    /// The whole image is decoded
+    Done,
+}
+
+/// A decoder that is handed the input as it arrives instead of reading it, for callers that
+/// can't block on a `Read`.
+///
+/// The headers, transformations, MANIAC trees and the position in the pixel data are kept
+/// between calls to `feed`, and input is dropped as soon as it is decoded. A part that runs out
+/// of input is rolled back and tried again once the input left over has doubled, so the last
+/// rows might only be decoded by `finish`.
+pub struct PushDecoder {
+    limits: Limits,
+    /// input that isn't decoded yet, the range coder takes it over for the pixel data
+    input: Input,
+    header: Option<Header>,
+    metadata: Option<Vec<Metadata>>,
+    pixels: Option<Box<PushImage>>,
+    /// amount of input that has to be there before decoding is tried again
+    wanted: usize,
+}
+
+impl Default for PushDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PushDecoder {
+    pub fn new() -> Self {
+        Self::with_limits(Default::default())
+    }
+
+    pub fn with_limits(limits: Limits) -> Self {
+        PushDecoder {
+            limits,
+            input: Default::default(),
+            header: None,
+            metadata: None,
+            pixels: None,
+            wanted: 0,
+        }
+    }
+
+    /// Adds `data` to the input and decodes as far as it goes. Input after the end of the
+    /// image is ignored.
+    pub fn feed(&mut self, data: &[u8]) -> Result<Progress> {
+        if self.progress() == Progress::Done {
+            return Ok(Progress::Done);
+        }
+
+        let input = match self.pixels {
+            Some(ref mut pixels) => pixels.coder.0.get_mut(),
+            None => &mut self.input,
+        };
+        input.push(data);
+        if input.available() >= self.wanted {
+            self.decode()?;
+        }
+
+        Ok(self.progress())
+    }
+
+    pub fn progress(&self) -> Progress {
+        match self.pixels {
+            Some(ref pixels) if pixels.done => Progress::Done,
+            Some(ref pixels) if pixels.info.header.interlaced => Progress::Rows(0),
+            Some(ref pixels) => match *pixels.image.position() {
+                Position::Start | Position::Trees { .. } => Progress::Trees,
+                _ => Progress::Rows(pixels.rows),
+            },
+            None if self.metadata.is_some() => Progress::SecondHeader,
+            None if self.header.is_some() => Progress::Metadata,
+            None => Progress::Header,
+        }
+    }
+
+    /// Image info, available once the second header is decoded
+    pub fn info(&self) -> Option<&FlifInfo> {
+        self.pixels.as_ref().map(|pixels| &pixels.info)
+    }
+
+    /// Raw pixel data of row `y` of `frame`, laid out like `Frame::raw`. `None` until the
+    /// row is complete.
+    pub fn row(&self, frame: usize, y: u32) -> Option<&[u8]> {
+        let pixels = self.pixels.as_ref()?;
+        if y >= pixels.rows {
+            return None;
+        }
+
+        let raw = pixels.raw.get(frame)?;
+        let row_len = raw.len() / pixels.rows as usize;
+        Some(&raw[y as usize * row_len..(y as usize + 1) * row_len])
+    }
+
+    /// Ends the input and decodes whatever is left of the image. Returns `Error::Incomplete`
+    /// if the input ended before the image did.
+    pub fn finish(mut self) -> Result<Flif> {
+        match self.pixels {
+            Some(ref mut pixels) => pixels.coder.0.get_mut().finished = true,
+            None => self.input.finished = true,
+        }
+        self.decode()?;
+
+        let pixels = match self.pixels {
+            Some(pixels) if pixels.done => pixels,
+            _ => return Err(Error::Incomplete),
+        };
+        let header = pixels.info.header;
+        let delays = pixels.info.second_header.frame_delay.as_ref();
+        let high_depth = high_depth(&pixels.info);
+        let frames = pixels
+            .raw
+            .into_iter()
+            .enumerate()
+            .map(|(i, raw)| {
+                let data = if high_depth {
+                    let raw = raw
+                        .chunks_exact(2)
+                        .map(|bytes| u16::from_ne_bytes([bytes[0], bytes[1]]))
+                        .collect();
+                    FrameData::Sixteen(raw)
+                } else {
+                    FrameData::Eight(raw.into_boxed_slice())
+                };
+                Frame::new(data, &header, delays.map_or(0, |delays| delays[i]))
+            })
+            .collect();
+
+        Ok(Flif {
+            info: pixels.info,
+            frames,
+        })
+    }
+
+    fn decode(&mut self) -> Result<()> {
+        if self.progress() == Progress::Done {
+            return Ok(());
+        }
+        if self.pixels.is_none() {
+            self.decode_headers()?;
+        }
+
+        if let Some(ref mut pixels) = self.pixels {
+            pixels.resume(&self.limits)?;
+            // whatever stopped the decoder needs more than the input that is left
+            self.wanted = 2 * pixels.coder.0.get_ref().available();
+        }
+
+        Ok(())
+    }
+
+    /// Decodes the headers that are complete, up to the second header
+    fn decode_headers(&mut self) -> Result<()> {
+        let limits = &self.limits;
+        // the headers are only decoded in one go, so wait for a good deal more input
+        self.wanted = 2 * self.input.available();
+
+        if self.header.is_none() {
+            match self
+                .input
+                .attempt(|input| Header::from_reader(input, limits))?
+            {
+                Some(header) => self.header = Some(header),
+                None => return Ok(()),
+            }
+        }
+        let main_header = self.header.expect("the header was just decoded");
+
+        if self.metadata.is_none() {
+            let metadata = match self
+                .input
+                .attempt(|input| Metadata::all_from_reader(input, limits))?
+            {
+                Some(metadata) => metadata,
+                None => return Ok(()),
+            };
+            if metadata.1 != 0 {
+                return Err(Error::UnknownRequiredMetadata(metadata.1));
+            }
+            self.metadata = Some(metadata.0);
+        }
+
+        // the range coder reads ahead, so it gets all of the input
+        let start = self.input.pos;
+        let mut rac = Rac::from_reader(mem::take(&mut self.input))?;
+        let (second_header, transform) = match SecondHeader::from_rac(&main_header, &mut rac) {
+            _ if rac.is_truncated() => {
+                if rac.get_ref().finished {
+                    return Err(Error::Incomplete);
+                }
+                self.input = rac.into_inner();
+                self.input.pos = start;
+                return Ok(());
+            }
+            result => result?,
+        };
+
+        let info = FlifInfo {
+            header: main_header,
+            metadata: self.metadata.take().unwrap_or_default(),
+            second_header,
+            transform,
+        };
+        let image: Box<dyn PartialImage> = match info.header.channels {
+            _ if info.transform.max_lookback().is_some() => {
+                Box::new(Partial::<RgbaLookback>::new(&info))
+            }
+            ColorSpace::Monochrome => Box::new(Partial::<Greyscale>::new(&info)),
+            ColorSpace::RGB => Box::new(Partial::<Rgb>::new(&info)),
+            ColorSpace::RGBA => Box::new(Partial::<Rgba>::new(&info)),
+        };
+        let update_table =
+            UpdateTable::new(info.second_header.alpha_divisor, info.second_header.cutoff);
+
+        self.pixels = Some(Box::new(PushImage {
+            raw: vec![Vec::new(); info.header.num_frames as usize],
+            info,
+            update_table,
+            coder: PushCoder(rac, None),
+            image,
+            rows: 0,
+            done: false,
+        }));
+        Ok(())
+    }
+}
+
+/// The pixel data of a `PushDecoder` and everything needed to go on decoding it
+struct PushImage {
+    info: FlifInfo,
+    update_table: UpdateTable,
+    coder: PushCoder,
+    image: Box<dyn PartialImage>,
+    /// final rows of every frame, laid out like `Frame::raw`
+    raw: Vec<Vec<u8>>,
+    rows: u32,
+    done: bool,
+}
+
+impl PushImage {
+    fn resume(&mut self, limits: &Limits) -> Result<()> {
+        let mut row = Vec::new();
+        let raw = &mut self.raw;
+        let rows = &mut self.rows;
+        let done = self.image.resume(
+            &self.info,
+            &mut self.coder,
+            limits,
+            &self.update_table,
+            &mut |image: &dyn RawRows, y| {
+                for (fr, raw) in raw.iter_mut().enumerate() {
+                    image.raw_row(fr, y, &mut row);
+                    raw.extend_from_slice(&row);
+                }
+                *rows = y + 1;
+            },
+        );
+
+        // anything tentative got rolled back, so this only happens once the input is finished
+        if self.coder.0.is_truncated() {
+            return Err(Error::Incomplete);
+        }
+        self.done = done?;
+        Ok(())
+    }
+}
+
+/// Rows of an image, with the transformations undone
+trait RawRows {
+    fn raw_row(&self, fr: usize, y: u32, raw: &mut Vec<u8>);
+}
+
+impl<P: Pixel, C: PixelCoder> RawRows for CodingImage<'_, P, C> {
+    fn raw_row(&self, fr: usize, y: u32, raw: &mut Vec<u8>) {
+        CodingImage::raw_row(self, fr, y, raw)
+    }
+}
+
+/// `Partial` without the type of its pixels
+trait PartialImage {
+    fn position(&self) -> &Position;
+
+    /// Decodes as far as the input goes and hands every row that became final to `row`,
+    /// returns whether the image is complete
+    fn resume(
+        &mut self,
+        info: &FlifInfo,
+        coder: &mut PushCoder,
+        limits: &Limits,
+        update_table: &UpdateTable,
+        row: &mut dyn FnMut(&dyn RawRows, u32),
+    ) -> Result<bool>;
+}
+
+/// An image that is decoded up to `position`
+struct Partial<P: Pixel> {
+    data: Vec<P>,
+    maniac: ManiacForest,
+    position: Position,
+}
+
+impl<P: Pixel> Partial<P> {
+    fn new(info: &FlifInfo) -> Self {
+        let pixels = (info.header.width * info.header.height) as usize;
+        Partial {
+            data: vec![P::default(); pixels * info.header.num_frames as usize],
+            maniac: Default::default(),
+            position: Position::Start,
+        }
+    }
+}
+
+impl<P: Pixel> PartialImage for Partial<P> {
+    fn position(&self) -> &Position {
+        &self.position
+    }
+
+    fn resume(
+        &mut self,
+        info: &FlifInfo,
+        coder: &mut PushCoder,
+        limits: &Limits,
+        update_table: &UpdateTable,
+        row: &mut dyn FnMut(&dyn RawRows, u32),
+    ) -> Result<bool> {
+        let data = mem::take(&mut self.data);
+        let mut image = CodingImage::<P, _>::with_data(info, coder, limits, update_table, data)?;
+        let result = loop {
+            match image.advance(&mut self.position, &mut self.maniac) {
+                Ok(Advance::Row { y, last: true }) => row(&image, y),
+                Ok(Advance::Done) => {
+                    if info.header.interlaced {
+                        for y in 0..info.header.height {
+                            row(&image, y);
+                        }
+                    }
+                    break Ok(true);
+                }
+                Ok(Advance::Suspended) => break Ok(false),
+                Ok(_) => {}
+                Err(err) => break Err(err),
+            }
+        };
+
+        self.data = image.into_data();
+        result
+    }
+}
+
+/// Input of a `PushDecoder` that isn't decoded yet
+#[derive(Debug, Default)]
+struct Input {
+    data: Vec<u8>,
+    pos: usize,
+    /// whether all of the input is there
+    finished: bool,
+}
+
+impl Input {
+    fn available(&self) -> usize {
+        self.data.len() - self.pos
+    }
+
+    /// Drops the input that is decoded and adds `data`
+    fn push(&mut self, data: &[u8]) {
+        self.data.drain(..self.pos);
+        self.pos = 0;
+        self.data.extend_from_slice(data);
+    }
+
+    /// Decodes with `decode`, or goes back to where it started if the input ends first
+    fn attempt<T, F>(&mut self, decode: F) -> Result<Option<T>>
+    where
+        F: FnOnce(&mut Self) -> Result<T>,
+    {
+        let start = self.pos;
+        match decode(self) {
+            Err(Error::Io(ref err)) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                self.pos = start;
+                Ok(None)
+            }
+            result => result.map(Some),
+        }
+    }
+}
+
+impl Read for Input {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = (&self.data[self.pos..]).read(buf)?;
+        self.pos += read;
+        Ok(read)
+    }
+}
+
+/// Range coder over the input of a `PushDecoder`, which can go back to a checkpoint as long
+/// as more input might follow
+struct PushCoder(Rac<Input>, Option<(RacState, usize)>);
+
+impl PixelCoder for PushCoder {
+    fn code_tree(
+        &mut self,
+        channel: RgbaChannels,
+        info: &FlifInfo,
+        update_table: &UpdateTable,
+        limits: &Limits,
+    ) -> Result<ManiacTree> {
+        self.0.code_tree(channel, info, update_table, limits)
+    }
+
+    fn code_pixel(
+        &mut self,
+        channel: RgbaChannels,
+        maniac: &mut ManiacTree,
+        pvec: &[ColorValue],
+        guess: ColorValue,
+        range: ColorRange,
+        value: ColorValue,
+    ) -> Result<ColorValue> {
+        self.0
+            .code_pixel(channel, maniac, pvec, guess, range, value)
+    }
+
+    fn code_near_zero(
+        &mut self,
+        range: ColorRange,
+        value: ColorValue,
+        context: &mut ChanceTable,
+    ) -> Result<ColorValue> {
+        self.0.code_near_zero(range, value, context)
+    }
+
+    fn code_val<T: PrimInt>(&mut self, min: T, max: T, value: T) -> Result<T> {
+        self.0.code_val(min, max, value)
+    }
+
+    fn code_bool(&mut self, value: bool) -> Result<bool> {
+        self.0.code_bool(value)
+    }
+
+    fn exhausted(&self) -> bool {
+        self.0.is_truncated()
+    }
+
+    fn has_input(&self, bytes: usize) -> bool {
+        let input = self.0.get_ref();
+        input.finished || input.available() >= bytes
+    }
+
+    fn checkpoint(&mut self) {
+        self.1 = Some((self.0.state(), self.0.get_ref().pos));
+    }
+
+    fn rollback(&mut self) -> bool {
+        match self.1.take() {
+            Some((state, pos)) if self.0.is_truncated() && !self.0.get_ref().finished => {
+                self.0.rewind(state);
+                self.0.get_mut().pos = pos;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+fn high_depth(info: &FlifInfo) -> bool {
+    info.second_header
+        .bits_per_pixel
+        .iter()
+        .any(|&bits| bits > 8)
+}
@@ -25,3 +25,24 @@ fn alpha_zero_test() {
         assert_eq!(&p[..3], &f[..3]);
     }
 }
+
+/// The file is written by this crate's encoder, so it only guards against regressions
+#[test]
+fn alpha_zero_interlaced_test() {
+    let png_data = include_bytes!("../../resources/rust_logo.png").as_ref();
+    let flif_data =
+        include_bytes!("../../resources/rust_logo_interlaced_discard_invisible.flif").as_ref();
+    let png_frame = decode_png(png_data);
+    let image = Flif::decode(flif_data).unwrap();
+
+    let info = image.info();
+    assert!(info.header.interlaced && info.second_header.alpha_zero);
+    assert!(info.second_header.invis_pixel_predictor.is_some());
+    for (p, f) in png_frame.chunks(4).zip(image.raw().chunks(4)) {
+        assert_eq!(p[3], f[3]);
+        if p[3] == 0 {
+            continue;
+        }
+        assert_eq!(&p[..3], &f[..3]);
+    }
+}
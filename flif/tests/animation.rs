@@ -0,0 +1,19 @@
+extern crate flif;
+
+use std::fs::File;
+use std::io::BufReader;
+
+use flif::Flif;
+
+#[test]
+fn still_image_has_single_frame() {
+    let file = BufReader::new(File::open("../resources/sea_snail.flif").unwrap());
+    let image = Flif::decode(file).unwrap();
+
+    assert_eq!(image.loops(), None);
+    assert_eq!(image.frames().len(), 1);
+
+    let frame = image.frames().next().unwrap();
+    assert_eq!(frame.delay(), 0);
+    assert_eq!(frame.raw(), image.raw());
+}
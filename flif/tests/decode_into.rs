@@ -0,0 +1,79 @@
+extern crate flif;
+
+use flif::{Decoder, Error, Flif, PixelFormat, PixelLayout};
+
+#[test]
+fn decode_into_rgba_with_stride() {
+    let data = include_bytes!("../../resources/sea_snail_cutout.flif");
+    let full = Flif::decode(&data[..]).unwrap();
+    let (width, height) = (
+        full.info().header.width as usize,
+        full.info().header.height as usize,
+    );
+    let channels = full.info().header.channels as usize;
+    assert_eq!(3, channels);
+    let stride = width * 4 + 16;
+
+    let mut buf = vec![0xAA; stride * height];
+    let layout = PixelLayout::new(PixelFormat::Rgba8).stride(stride);
+    Decoder::new(&data[..])
+        .unwrap()
+        .decode_into(&mut buf, layout)
+        .unwrap();
+
+    for (y, row) in buf.chunks(stride).enumerate() {
+        for x in 0..width {
+            let src = &full.raw()[(y * width + x) * channels..][..channels];
+            let out = &row[x * 4..x * 4 + 4];
+            assert_eq!([src[0], src[1], src[2], 0xFF], out);
+        }
+        // the padding is left alone
+        assert!(row[width * 4..].iter().all(|&b| b == 0xAA));
+    }
+}
+
+#[test]
+fn decode_into_bgra() {
+    let data = include_bytes!("../../resources/rust_logo.flif");
+    let full = Flif::decode(&data[..]).unwrap();
+    assert_eq!(4, full.info().header.channels as usize);
+
+    let mut buf = vec![0; full.raw().len()];
+    Decoder::new(&data[..])
+        .unwrap()
+        .decode_into(&mut buf, PixelLayout::new(PixelFormat::Bgra8))
+        .unwrap();
+
+    for (src, out) in full.raw().chunks(4).zip(buf.chunks(4)) {
+        assert_eq!([src[2], src[1], src[0], src[3]], out);
+    }
+}
+
+#[test]
+fn decode_into_grey() {
+    let data = include_bytes!("../../resources/road.flif");
+    let full = Flif::decode(&data[..]).unwrap();
+    assert_eq!(1, full.info().header.channels as usize);
+
+    let mut buf = vec![0; full.raw().len()];
+    Decoder::new(&data[..])
+        .unwrap()
+        .decode_into(&mut buf, PixelLayout::new(PixelFormat::Grey8))
+        .unwrap();
+    assert!(buf.as_slice() == full.raw());
+}
+
+#[test]
+fn decode_into_small_buffer() {
+    let data = include_bytes!("../../resources/road.flif");
+    let header = Decoder::new(&data[..]).unwrap().info().header;
+    let mut buf = vec![0; (header.width * header.height) as usize - 1];
+
+    match Decoder::new(&data[..])
+        .unwrap()
+        .decode_into(&mut buf, PixelLayout::new(PixelFormat::Grey8))
+    {
+        Err(Error::InvalidOperation(_)) => {}
+        _ => panic!("expected an Error::InvalidOperation for a small buffer"),
+    }
+}
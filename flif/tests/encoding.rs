@@ -0,0 +1,453 @@
+extern crate flif;
+extern crate png;
+
+use flif::components::{ChunkType, ColorSpace, Metadata, Transformation};
+use flif::{Encoder, EncoderOptions, Error, Flif, Limits};
+
+fn decode_png(png_data: &[u8]) -> (png::OutputInfo, Box<[u8]>) {
+    let decoder = png::Decoder::new(png_data);
+    let (info, mut reader) = decoder.read_info().unwrap();
+    let mut buf = vec![0; info.buffer_size()];
+    reader.next_frame(&mut buf).unwrap();
+    (info, buf.into_boxed_slice())
+}
+
+fn round_trip(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    channels: ColorSpace,
+    interlaced: bool,
+) -> Flif {
+    let encoded = Encoder::new(Vec::new(), width, height, channels)
+        .unwrap()
+        .interlaced(interlaced)
+        .encode(data)
+        .unwrap();
+    let image = Flif::decode(encoded.as_slice()).unwrap();
+
+    let header = &image.info().header;
+    assert_eq!((width, height), (header.width, header.height));
+    assert_eq!(channels, header.channels);
+    assert_eq!(interlaced, header.interlaced);
+    assert!(image.raw() == data, "decoded pixels differ from the input");
+    image
+}
+
+fn round_trip_png(png_data: &[u8], interlaced: bool) -> Flif {
+    let (info, data) = decode_png(png_data);
+    let channels = match info.color_type {
+        png::ColorType::Grayscale => ColorSpace::Monochrome,
+        png::ColorType::RGB => ColorSpace::RGB,
+        png::ColorType::RGBA => ColorSpace::RGBA,
+        _ => unreachable!(),
+    };
+    round_trip(&data, info.width, info.height, channels, interlaced)
+}
+
+#[test]
+fn encode_rgba() {
+    round_trip_png(include_bytes!("../../resources/rust_logo.png"), false);
+}
+
+#[test]
+fn encode_rgb() {
+    round_trip_png(include_bytes!("../../resources/sea_snail.png"), false);
+}
+
+#[test]
+fn encode_greyscale() {
+    round_trip_png(include_bytes!("../../resources/road.png"), false);
+}
+
+#[test]
+fn encode_palette() {
+    let image = round_trip_png(include_bytes!("../../resources/flif_logo.png"), false);
+    let transformations = &image.info().second_header.transformations;
+    assert!(transformations.contains(&Transformation::Palette));
+}
+
+#[test]
+fn encode_compact_color() {
+    // too many colors for a palette, but every channel only uses a few values
+    let data = (0..32 * 32)
+        .flat_map(|i| {
+            let (x, y) = (i % 32, i / 32);
+            vec![(x * 8) as u8, (y * 8) as u8, ((x + y) * 4) as u8]
+        })
+        .collect::<Vec<u8>>();
+    let image = round_trip(&data, 32, 32, ColorSpace::RGB, false);
+    let transformations = &image.info().second_header.transformations;
+    assert_eq!(
+        &[Transformation::ChannelCompact, Transformation::YCoGg],
+        &transformations[..2]
+    );
+}
+
+/// Checks that the encoder still writes `golden` for road.png, so that changes to the output
+/// are noticed
+fn encode_golden(golden: &[u8], interlaced: bool) {
+    let (info, data) = decode_png(include_bytes!("../../resources/road.png"));
+    let encoded = Encoder::new(Vec::new(), info.width, info.height, ColorSpace::Monochrome)
+        .unwrap()
+        .interlaced(interlaced)
+        .encode(&data)
+        .unwrap();
+    assert!(
+        encoded == golden,
+        "encoded file differs from the golden file"
+    );
+}
+
+#[test]
+fn encode_golden_file() {
+    encode_golden(include_bytes!("../../resources/road_golden.flif"), false);
+}
+
+#[test]
+fn encode_interlaced_golden_file() {
+    encode_golden(
+        include_bytes!("../../resources/road_interlaced_golden.flif"),
+        true,
+    );
+}
+
+#[test]
+fn encode_interlaced_rgba() {
+    round_trip_png(include_bytes!("../../resources/rust_logo.png"), true);
+}
+
+#[test]
+fn encode_interlaced_greyscale() {
+    round_trip_png(include_bytes!("../../resources/road.png"), true);
+}
+
+#[test]
+fn encode_interlaced_single_pixel() {
+    round_trip(&[12, 34, 56, 78], 1, 1, ColorSpace::RGBA, true);
+}
+
+#[test]
+fn encode_single_pixel() {
+    round_trip(&[12, 34, 56, 78], 1, 1, ColorSpace::RGBA, false);
+}
+
+fn psnr(a: &[u8], b: &[u8]) -> f64 {
+    let squared_error: f64 = a
+        .iter()
+        .zip(b)
+        .map(|(&a, &b)| (f64::from(a) - f64::from(b)).powi(2))
+        .sum();
+    10.0 * (255.0 * 255.0 * a.len() as f64 / squared_error).log10()
+}
+
+fn lossy_round_trip(png_data: &[u8], interlaced: bool) {
+    let (info, data) = decode_png(png_data);
+    let channels = match info.color_type {
+        png::ColorType::Grayscale => ColorSpace::Monochrome,
+        png::ColorType::RGB => ColorSpace::RGB,
+        _ => ColorSpace::RGBA,
+    };
+    let lossless = Encoder::new(Vec::new(), info.width, info.height, channels)
+        .unwrap()
+        .interlaced(interlaced)
+        .encode(&data)
+        .unwrap();
+    let (lossy, reported) = Encoder::new(Vec::new(), info.width, info.height, channels)
+        .unwrap()
+        .interlaced(interlaced)
+        .quality(60)
+        .encode_with_psnr(&data)
+        .unwrap();
+    let image = Flif::decode(lossy.as_slice()).unwrap();
+
+    assert!(lossy.len() < lossless.len());
+    let measured = psnr(&data, image.raw());
+    assert!((measured - reported).abs() < 1e-6);
+    assert!(reported > 25.0 && reported.is_finite());
+}
+
+#[test]
+fn encode_lossy() {
+    lossy_round_trip(
+        include_bytes!("../../resources/sea_snail_cutout.png"),
+        false,
+    );
+}
+
+#[test]
+fn encode_lossy_interlaced() {
+    lossy_round_trip(include_bytes!("../../resources/road.png"), true);
+}
+
+#[test]
+fn encode_lossless_psnr() {
+    let (_, psnr) = Encoder::new(Vec::new(), 1, 1, ColorSpace::RGB)
+        .unwrap()
+        .encode_with_psnr(&[1, 2, 3])
+        .unwrap();
+    assert_eq!(f64::INFINITY, psnr);
+}
+
+/// Three frames of a gradient, the second one paints a square over the first one and the
+/// third one repeats the first one
+fn animation_frames() -> Vec<Vec<u8>> {
+    let (width, height) = (40, 30);
+    let first = (0..width * height)
+        .flat_map(|i| {
+            let (x, y) = (i % width, i / width);
+            vec![(x * 6) as u8, (y * 8) as u8, 128, 255]
+        })
+        .collect::<Vec<u8>>();
+    let mut second = first.clone();
+    for y in 10..20 {
+        for x in 5..15 {
+            let i = (y * width + x) * 4;
+            second[i..i + 4].copy_from_slice(&[255, 0, 0, 200]);
+        }
+    }
+
+    vec![first.clone(), second, first]
+}
+
+fn round_trip_animation(interlaced: bool) {
+    let frames = animation_frames();
+    let frames = frames.iter().map(Vec::as_slice).collect::<Vec<_>>();
+    let delays = [100, 250, 40];
+    let encoded = Encoder::new(Vec::new(), 40, 30, ColorSpace::RGBA)
+        .unwrap()
+        .interlaced(interlaced)
+        .loops(3)
+        .encode_frames(&frames, &delays)
+        .unwrap();
+    let image = Flif::decode(encoded.as_slice()).unwrap();
+
+    let transformations = &image.info().second_header.transformations;
+    assert!(transformations.contains(&Transformation::DuplicateFrame));
+    assert!(transformations.contains(&Transformation::FrameShape));
+    assert_eq!(Some(3), image.loops());
+    assert_eq!(3, image.frames().count());
+    for ((frame, data), &delay) in image.frames().zip(&frames).zip(&delays) {
+        assert!(frame.raw() == *data, "decoded frame differs from the input");
+        assert_eq!(delay, frame.delay());
+    }
+}
+
+#[test]
+fn encode_animation() {
+    round_trip_animation(false);
+}
+
+#[test]
+fn encode_interlaced_animation() {
+    round_trip_animation(true);
+}
+
+#[test]
+fn encode_with_options() {
+    let (info, data) = decode_png(include_bytes!("../../resources/road.png"));
+    let options = EncoderOptions {
+        effort: 0,
+        cutoff: 4,
+        alpha_divisor: 30,
+        transformations: Vec::new(),
+        ..Default::default()
+    };
+    let encoded = Encoder::with_options(
+        Vec::new(),
+        info.width,
+        info.height,
+        ColorSpace::Monochrome,
+        options,
+    )
+    .unwrap()
+    .encode(&data)
+    .unwrap();
+    let image = Flif::decode(encoded.as_slice()).unwrap();
+
+    let second_header = &image.info().second_header;
+    assert!(second_header.custom_cutoff);
+    assert_eq!((4, 30), (second_header.cutoff, second_header.alpha_divisor));
+    assert!(second_header.transformations.is_empty());
+    assert!(
+        image.raw() == &data[..],
+        "decoded pixels differ from the input"
+    );
+}
+
+#[test]
+fn encode_max_tree_size() {
+    let (info, data) = decode_png(include_bytes!("../../resources/road.png"));
+    let options = EncoderOptions {
+        max_tree_size: 16,
+        ..Default::default()
+    };
+    let encoded = Encoder::with_options(
+        Vec::new(),
+        info.width,
+        info.height,
+        ColorSpace::Monochrome,
+        options,
+    )
+    .unwrap()
+    .encode(&data)
+    .unwrap();
+
+    let limits = Limits {
+        maniac_nodes: 16,
+        ..Default::default()
+    };
+    let image = Flif::decode_with_limits(encoded.as_slice(), limits).unwrap();
+    assert!(
+        image.raw() == &data[..],
+        "decoded pixels differ from the input"
+    );
+}
+
+fn round_trip_alpha_zero(interlaced: bool, invisible_predictor: u8) {
+    // a gradient with a transparent hole full of noise
+    let (width, height) = (48, 40);
+    let data = (0..width * height)
+        .flat_map(|i| {
+            let (x, y) = (i % width, i / width);
+            let inside = (x as i32 - 24).pow(2) + (y as i32 - 20).pow(2) < 100;
+            let alpha = if inside { 0 } else { 255 - y as u8 };
+            let noise = (i * 7919 % 251) as u8;
+            vec![(x * 5) as u8 ^ noise, (y * 6) as u8, noise, alpha]
+        })
+        .collect::<Vec<u8>>();
+    let options = EncoderOptions {
+        alpha_zero: true,
+        invisible_predictor,
+        ..Default::default()
+    };
+    let (encoded, psnr) =
+        Encoder::with_options(Vec::new(), width, height, ColorSpace::RGBA, options)
+            .unwrap()
+            .interlaced(interlaced)
+            .encode_with_psnr(&data)
+            .unwrap();
+    let image = Flif::decode(encoded.as_slice()).unwrap();
+
+    let second_header = &image.info().second_header;
+    assert!(second_header.alpha_zero);
+    if interlaced {
+        assert_eq!(
+            Some(invisible_predictor),
+            second_header.invis_pixel_predictor
+        );
+    }
+    assert_eq!(f64::INFINITY, psnr);
+    for (input, decoded) in data.chunks(4).zip(image.raw().chunks(4)) {
+        if input[3] == 0 {
+            assert_eq!(0, decoded[3]);
+        } else {
+            assert_eq!(input, decoded);
+        }
+    }
+}
+
+#[test]
+fn encode_alpha_zero() {
+    round_trip_alpha_zero(false, 0);
+}
+
+#[test]
+fn encode_interlaced_alpha_zero() {
+    for predictor in 0..=2 {
+        round_trip_alpha_zero(true, predictor);
+    }
+}
+
+#[test]
+fn encode_invalid_cutoff() {
+    let options = EncoderOptions {
+        cutoff: 0,
+        ..Default::default()
+    };
+    match Encoder::with_options(Vec::new(), 1, 1, ColorSpace::RGB, options) {
+        Err(Error::InvalidOperation(_)) => {}
+        _ => panic!("expected an Error::InvalidOperation for a cutoff of 0"),
+    }
+}
+
+#[test]
+fn encode_invalid_max_tree_size() {
+    let options = EncoderOptions {
+        max_tree_size: Limits::default().maniac_nodes + 1,
+        ..Default::default()
+    };
+    match Encoder::with_options(Vec::new(), 1, 1, ColorSpace::RGB, options) {
+        Err(Error::InvalidOperation(_)) => {}
+        _ => panic!("expected an Error::InvalidOperation for a tree size above the limits"),
+    }
+}
+
+#[test]
+fn encode_metadata() {
+    let icc = (0..3000).map(|i| (i % 7) as u8).collect::<Vec<u8>>();
+    let metadata = vec![
+        Metadata {
+            chunk_type: ChunkType::Iccp,
+            content: icc.clone(),
+        },
+        Metadata {
+            chunk_type: ChunkType::Exif,
+            content: b"Exif\0\0MM".to_vec(),
+        },
+        Metadata {
+            chunk_type: ChunkType::Unknown(*b"zzzz"),
+            content: Vec::new(),
+        },
+    ];
+    let encoded = Encoder::new(Vec::new(), 2, 1, ColorSpace::Monochrome)
+        .unwrap()
+        .metadata(metadata)
+        .encode(&[10, 20])
+        .unwrap();
+    let image = Flif::decode(encoded.as_slice()).unwrap();
+
+    let metadata = &image.info().metadata;
+    let names = metadata
+        .iter()
+        .map(|chunk| chunk.chunk_type.name())
+        .collect::<Vec<_>>();
+    assert_eq!(vec![*b"iCCP", *b"eXif", *b"zzzz"], names);
+    assert_eq!(icc, metadata[0].content);
+    assert_eq!(b"Exif\0\0MM", &metadata[1].content[..]);
+    assert!(metadata[2].content.is_empty());
+    assert_eq!(&[10, 20], image.raw());
+}
+
+#[test]
+fn encode_critical_metadata() {
+    let metadata = vec![Metadata {
+        chunk_type: ChunkType::Unknown(*b"ABCD"),
+        content: Vec::new(),
+    }];
+    let encoder = Encoder::new(Vec::new(), 1, 1, ColorSpace::Monochrome)
+        .unwrap()
+        .metadata(metadata);
+    match encoder.encode(&[0]) {
+        Err(Error::UnknownCriticalMetadata(_)) => {}
+        _ => panic!("expected an Error::UnknownCriticalMetadata for an uppercase chunk name"),
+    }
+}
+
+#[test]
+fn encode_missing_delay() {
+    let encoder = Encoder::new(Vec::new(), 1, 1, ColorSpace::Monochrome).unwrap();
+    match encoder.encode_frames(&[&[0], &[1]], &[10]) {
+        Err(Error::InvalidOperation(_)) => {}
+        _ => panic!("expected an Error::InvalidOperation for a frame without a delay"),
+    }
+}
+
+#[test]
+fn encode_wrong_length() {
+    let encoder = Encoder::new(Vec::new(), 2, 2, ColorSpace::RGB).unwrap();
+    match encoder.encode(&[0; 11]) {
+        Err(Error::InvalidOperation(_)) => {}
+        _ => panic!("expected an Error::InvalidOperation for too little pixel data"),
+    }
+}
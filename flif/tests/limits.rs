@@ -10,8 +10,10 @@ use flif::Limits;
 #[test]
 fn maniac_size_limit() {
     let file = BufReader::new(File::open("../resources/sea_snail.flif").unwrap());
-    let mut limits: Limits = Default::default();
-    limits.maniac_nodes = 16;
+    let limits = Limits {
+        maniac_nodes: 16,
+        ..Default::default()
+    };
     match Flif::decode_with_limits(file, limits) {
         Err(Error::LimitViolation(ref message)) if message.contains("maniac") => {}
         Err(err) => panic!(
@@ -0,0 +1,52 @@
+extern crate flif;
+
+use flif::{Color, Flif, Grey8, Pixels, Rgb8, Rgba8};
+
+#[test]
+fn pixels_grey() {
+    let image = Flif::decode(&include_bytes!("../../resources/road.flif")[..]).unwrap();
+    match image.pixels() {
+        Pixels::Grey8(pixels) => {
+            assert!(pixels.iter().map(|p| p.0).eq(image.raw().iter().cloned()));
+        }
+        pixels => panic!("expected greyscale pixels but got {:?}", pixels.get(0)),
+    }
+    assert_eq!(
+        Some(Color::Grey8(Grey8(image.raw()[0]))),
+        image.get_pixel(0, 0)
+    );
+}
+
+#[test]
+fn pixels_rgb() {
+    let image = Flif::decode(&include_bytes!("../../resources/sea_snail_cutout.flif")[..]).unwrap();
+    let width = image.info().header.width;
+    let (x, y) = (5, 7);
+    let idx = ((y * width + x) * 3) as usize;
+    let raw = &image.raw()[idx..idx + 3];
+
+    assert_eq!(
+        Some(Color::Rgb8(Rgb8([raw[0], raw[1], raw[2]]))),
+        image.get_pixel(x, y)
+    );
+    assert_eq!(None, image.get_pixel(width, 0));
+    assert_eq!(None, image.get_pixel(0, image.info().header.height));
+}
+
+#[test]
+fn pixels_rows() {
+    let image = Flif::decode(&include_bytes!("../../resources/rust_logo.flif")[..]).unwrap();
+    let header = image.info().header;
+    assert_eq!(header.height as usize, image.rows().len());
+
+    for (y, row) in image.rows().enumerate() {
+        assert_eq!(header.width as usize, row.len());
+        let x = y % header.width as usize;
+        let idx = (y * header.width as usize + x) * 4;
+        let raw = &image.raw()[idx..idx + 4];
+        assert_eq!(
+            Some(Color::Rgba8(Rgba8([raw[0], raw[1], raw[2], raw[3]]))),
+            row.get(x)
+        );
+    }
+}
@@ -0,0 +1,95 @@
+extern crate flif;
+extern crate png;
+
+use flif::components::ColorSpace;
+use flif::{Decoder, Encoder, Error, Flif};
+
+fn encode_interlaced(png_data: &[u8]) -> Vec<u8> {
+    let decoder = png::Decoder::new(png_data);
+    let (info, mut reader) = decoder.read_info().unwrap();
+    let mut data = vec![0; info.buffer_size()];
+    reader.next_frame(&mut data).unwrap();
+    let channels = match info.color_type {
+        png::ColorType::Grayscale => ColorSpace::Monochrome,
+        png::ColorType::RGB => ColorSpace::RGB,
+        _ => ColorSpace::RGBA,
+    };
+
+    Encoder::new(Vec::new(), info.width, info.height, channels)
+        .unwrap()
+        .interlaced(true)
+        .encode(&data)
+        .unwrap()
+}
+
+/// Checks that the pixels of `preview` on the grid of its zoomlevel match the full image
+fn assert_grid_matches(preview: &Flif, full: &Flif, zoomlevel: usize) {
+    let header = &full.info().header;
+    let channels = header.channels as usize;
+    let (row_step, col_step) = (1 << zoomlevel.div_ceil(2), 1 << (zoomlevel / 2));
+    for y in (0..header.height as usize).step_by(row_step) {
+        for x in (0..header.width as usize).step_by(col_step) {
+            let idx = (y * header.width as usize + x) * channels;
+            assert_eq!(
+                full.raw()[idx..idx + channels],
+                preview.raw()[idx..idx + channels],
+                "pixel ({}, {}) differs at zoomlevel {}",
+                x,
+                y,
+                zoomlevel
+            );
+        }
+    }
+}
+
+#[test]
+fn preview_complete() {
+    let encoded = encode_interlaced(include_bytes!("../../resources/road.png"));
+    let full = Flif::decode(encoded.as_slice()).unwrap();
+    let preview = Decoder::new(encoded.as_slice())
+        .unwrap()
+        .decode_preview(0)
+        .unwrap();
+
+    assert!(preview.is_complete());
+    assert_eq!(0, preview.zoomlevel());
+    assert!(preview.image().raw() == full.raw());
+}
+
+#[test]
+fn preview_zoomlevel() {
+    let encoded = encode_interlaced(include_bytes!("../../resources/sea_snail_cutout.png"));
+    let full = Flif::decode(encoded.as_slice()).unwrap();
+    let preview = Decoder::new(encoded.as_slice())
+        .unwrap()
+        .decode_preview(4)
+        .unwrap();
+
+    assert!(preview.is_complete());
+    assert!(preview.zoomlevel() <= 4);
+    assert_eq!(full.raw().len(), preview.image().raw().len());
+    assert_grid_matches(preview.image(), &full, preview.zoomlevel());
+}
+
+#[test]
+fn preview_truncated() {
+    let encoded = encode_interlaced(include_bytes!("../../resources/road.png"));
+    let full = Flif::decode(encoded.as_slice()).unwrap();
+    let truncated = &encoded[..encoded.len() / 4];
+    let decoder = Decoder::new(truncated).unwrap();
+    let max_zoomlevel = decoder.info().header.max_zoomlevel();
+    let preview = decoder.decode_preview(0).unwrap();
+
+    assert!(!preview.is_complete());
+    assert!(preview.zoomlevel() > 0 && preview.zoomlevel() < max_zoomlevel);
+    assert_grid_matches(preview.image(), &full, preview.zoomlevel());
+}
+
+#[test]
+fn preview_non_interlaced() {
+    let data = include_bytes!("../../resources/road.flif");
+    match Decoder::new(&data[..]).unwrap().decode_preview(0) {
+        Err(Error::InvalidOperation(_)) => {}
+        _ => panic!("expected an Error::InvalidOperation for a non-interlaced image"),
+    }
+}
@@ -0,0 +1,166 @@
+extern crate flif;
+
+use flif::components::ColorSpace;
+use flif::{Encoder, Error, Flif, Progress, PushDecoder};
+
+#[test]
+fn push_in_chunks() {
+    let data = include_bytes!("../../resources/road.flif");
+    let full = Flif::decode(&data[..]).unwrap();
+    let height = full.info().header.height;
+    let row_len = full.raw().len() / height as usize;
+
+    let mut decoder = PushDecoder::new();
+    let mut last_rows = 0;
+    for chunk in data.chunks(512) {
+        match decoder.feed(chunk).unwrap() {
+            Progress::Rows(rows) => {
+                assert!(rows >= last_rows);
+                last_rows = rows;
+            }
+            Progress::Done => last_rows = height,
+            _ => assert_eq!(0, last_rows),
+        }
+    }
+
+    assert!(last_rows > 0);
+    for y in 0..last_rows {
+        let start = y as usize * row_len;
+        assert_eq!(
+            &full.raw()[start..start + row_len],
+            decoder.row(0, y).unwrap()
+        );
+    }
+    assert!(decoder.finish().unwrap().raw() == full.raw());
+}
+
+#[test]
+fn push_complete() {
+    let data = include_bytes!("../../resources/rust_logo.flif");
+    let full = Flif::decode(&data[..]).unwrap();
+    let height = full.info().header.height;
+
+    let mut decoder = PushDecoder::new();
+    assert_eq!(Progress::Done, decoder.feed(&data[..]).unwrap());
+    assert!(decoder.row(0, height - 1).is_some());
+    assert!(decoder.row(0, height).is_none());
+    assert!(decoder.finish().unwrap().raw() == full.raw());
+}
+
+#[test]
+fn push_stages() {
+    let data = include_bytes!("../../resources/sea_snail_cutout.flif");
+    let mut decoder = PushDecoder::new();
+
+    assert_eq!(Progress::Header, decoder.feed(&data[..4]).unwrap());
+    assert!(decoder.info().is_none());
+    assert!(decoder.row(0, 0).is_none());
+
+    let mut stages = vec![Progress::Header];
+    for byte in data[4..].chunks(1) {
+        let progress = match decoder.feed(byte).unwrap() {
+            Progress::Rows(_) => Progress::Rows(0),
+            progress => progress,
+        };
+        if stages.last() != Some(&progress) {
+            stages.push(progress);
+        }
+    }
+    // stages may be passed within one feed, but never revisited
+    let order = [
+        Progress::Header,
+        Progress::Metadata,
+        Progress::SecondHeader,
+        Progress::Trees,
+        Progress::Rows(0),
+    ];
+    let ranks: Vec<_> = stages
+        .iter()
+        .map(|stage| order.iter().position(|o| o == stage).unwrap())
+        .collect();
+    assert!(ranks.windows(2).all(|w| w[0] < w[1]));
+    assert_eq!(Some(&Progress::Rows(0)), stages.last());
+}
+
+#[test]
+fn push_byte_by_byte() {
+    for data in &[
+        &include_bytes!("../../resources/road.flif")[..],
+        &include_bytes!("../../resources/sea_snail_cutout.flif")[..],
+        &include_bytes!("../../resources/rgba_edge.flif")[..],
+    ] {
+        let full = Flif::decode(*data).unwrap();
+        let mut decoder = PushDecoder::new();
+        for byte in data.chunks(1) {
+            decoder.feed(byte).unwrap();
+        }
+        assert!(decoder.finish().unwrap().raw() == full.raw());
+    }
+}
+
+#[test]
+fn push_rows_are_final() {
+    let data = include_bytes!("../../resources/sea_snail_cutout.flif");
+    let full = Flif::decode(&data[..]).unwrap();
+    let row_len = full.raw().len() / full.info().header.height as usize;
+
+    let mut decoder = PushDecoder::new();
+    let rows = match decoder.feed(&data[..data.len() * 9 / 10]).unwrap() {
+        Progress::Rows(rows) => rows,
+        progress => panic!("expected complete rows but got {:?}", progress),
+    };
+    assert!(rows > 0);
+    for y in 0..rows {
+        let start = y as usize * row_len;
+        assert_eq!(
+            &full.raw()[start..start + row_len],
+            decoder.row(0, y).unwrap()
+        );
+    }
+    assert!(decoder.row(0, rows).is_none());
+}
+
+#[test]
+fn push_finish_incomplete() {
+    let data = include_bytes!("../../resources/road.flif");
+    for &len in &[3, 20, data.len() / 3, data.len() * 2 / 3] {
+        let mut decoder = PushDecoder::new();
+        decoder.feed(&data[..len]).unwrap();
+        match decoder.finish() {
+            Err(Error::Incomplete) => {}
+            result => panic!(
+                "expected Error::Incomplete for {} bytes, got {:?}",
+                len,
+                result.map(|_| ())
+            ),
+        }
+    }
+}
+
+#[test]
+fn push_interlaced() {
+    let (width, height) = (48, 40);
+    let data: Vec<u8> = (0..width * height * 3)
+        .map(|i| ((i * 7) ^ (i / 97)) as u8)
+        .collect();
+    let encoded = Encoder::new(Vec::new(), width, height, ColorSpace::RGB)
+        .unwrap()
+        .interlaced(true)
+        .encode(&data)
+        .unwrap();
+
+    let mut decoder = PushDecoder::new();
+    assert_eq!(
+        Progress::Rows(0),
+        decoder.feed(&encoded[..encoded.len() / 2]).unwrap()
+    );
+    assert!(decoder.row(0, 0).is_none());
+    decoder.feed(&encoded[encoded.len() / 2..]).unwrap();
+    assert_eq!(data.as_slice(), &*decoder.finish().unwrap().into_raw());
+
+    let mut decoder = PushDecoder::new();
+    for chunk in encoded.chunks(7) {
+        decoder.feed(chunk).unwrap();
+    }
+    assert_eq!(data.as_slice(), &*decoder.finish().unwrap().into_raw());
+}
@@ -0,0 +1,60 @@
+extern crate flif;
+
+use flif::components::ColorSpace;
+use flif::{Decoder, Encoder, Error, Flif};
+
+fn collect_rows(data: &[u8]) -> Vec<u8> {
+    let mut raw = Vec::new();
+    let mut next = 0;
+    Decoder::new(data)
+        .unwrap()
+        .decode_rows(|y, row| {
+            assert_eq!(next, y);
+            next += 1;
+            raw.extend_from_slice(row);
+        })
+        .unwrap();
+    raw
+}
+
+#[test]
+fn rows_match_raw() {
+    for data in &[
+        &include_bytes!("../../resources/road.flif")[..],
+        &include_bytes!("../../resources/rust_logo.flif")[..],
+        &include_bytes!("../../resources/flif_logo.flif")[..],
+    ] {
+        let full = Flif::decode(*data).unwrap();
+        assert!(collect_rows(data) == full.raw());
+    }
+}
+
+#[test]
+fn rows_interlaced() {
+    let (width, height) = (40, 24);
+    let data: Vec<u8> = (0..width * height).map(|i| (i * 5 % 251) as u8).collect();
+    let encoded = Encoder::new(Vec::new(), width, height, ColorSpace::Monochrome)
+        .unwrap()
+        .interlaced(true)
+        .encode(&data)
+        .unwrap();
+
+    assert_eq!(data, collect_rows(&encoded));
+}
+
+#[test]
+fn rows_animation() {
+    let frame = vec![0x80; 8 * 8 * 3];
+    let encoded = Encoder::new(Vec::new(), 8, 8, ColorSpace::RGB)
+        .unwrap()
+        .encode_frames(&[&frame, &frame], &[100, 100])
+        .unwrap();
+
+    match Decoder::new(encoded.as_slice())
+        .unwrap()
+        .decode_rows(|_, _| {})
+    {
+        Err(Error::InvalidOperation(_)) => {}
+        _ => panic!("expected an Error::InvalidOperation for an animation"),
+    }
+}